@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rusqlite::Connection;
+use uvp::data::{add_entry_to_available, add_to_feed, init_db, make_active, Feed};
+use uvp::feeds::Entry;
+use uvp::tui::Bench;
+use uvp::Settings;
+
+const FEEDS: usize = 50;
+const ENTRIES: usize = 10_000;
+
+/// An in-memory database with ENTRIES entries spread over FEEDS feeds, every tenth of which is
+/// active.
+fn synthetic_store() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn).unwrap();
+    let epoch = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00+00:00").unwrap();
+    for f in 0..FEEDS {
+        let feed = Feed {
+            title: format!("Feed {}", f),
+            url: format!("https://example.com/feed/{}", f),
+            lastupdate: None,
+        };
+        add_to_feed(&conn, &feed).unwrap();
+        for e in (f..ENTRIES).step_by(FEEDS) {
+            let entry = Entry {
+                title: format!("Video number {} of feed {}", e, f),
+                url: format!("https://example.com/video/{}", e),
+                publication: epoch + chrono::Duration::minutes(e as i64),
+                duration_secs: Some(60.0 * (e % 90) as f64),
+                short_link: false,
+                guid: None,
+                description: None,
+            };
+            add_entry_to_available(&conn, feed.url.clone(), &entry).unwrap();
+            if e % 10 == 0 {
+                make_active(&conn, &entry.url).unwrap();
+            }
+        }
+    }
+    conn
+}
+
+fn tui(c: &mut Criterion) {
+    let conn = synthetic_store();
+    let settings = Settings::default();
+    let mut tui = Bench::new(&conn, &settings).unwrap();
+
+    c.bench_function("update", |b| b.iter(|| tui.update(&conn).unwrap()));
+    c.bench_function("rebuild", |b| b.iter(|| tui.rebuild()));
+    c.bench_function("filter", |b| {
+        b.iter(|| {
+            tui.filter("feed 1");
+            tui.filter("");
+        })
+    });
+}
+
+criterion_group!(benches, tui);
+criterion_main!(benches);
@@ -0,0 +1,247 @@
+//! Backups of the database as a versioned json document:
+//!
+//! ```json
+//! {"version": 1, "feeds": [...], "available": [...], "active": [...]}
+//! ```
+//!
+//! Feeds and available videos use the same fields as in hooks (see `hooks::feed_json`), available
+//! videos additionally contain the url of their feed (`feedurl`). Active videos are stored in
+//! insertion order.
+//!
+//! The credentials of feeds (see `data::FeedAuth`), the errors of their last refresh and which
+//! entries of them have been seen are not included. Neither are downloads, removed videos and
+//! other state of the database.
+//!
+//! Importing a backup merges it into the database: Existing feeds and videos are kept, except
+//! for active videos that have been played further in the backup. Videos that are active in
+//! either the database or the backup are not made available again. Replacing the data instead
+//! also clears the removed videos and playback leases, which refer to the replaced videos. The
+//! downloads are kept, so that their files are still deleted according to the usual rules.
+use crate::data::{
+    add_entry_to_available, add_to_active, add_to_feed, find_feed, find_in_active, in_transaction,
+    iter_active, iter_available, iter_feeds, remove_from_available, set_chapter, set_lastupdate,
+    set_position_secs, Active, ActiveOrder, DateTime, Feed,
+};
+use crate::feeds::Entry;
+use crate::{ignore_constraint_errors, Error};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+/// Version of the backup format, increased on incompatible changes.
+pub const VERSION: u64 = 1;
+
+fn active_json(active: &Active) -> Value {
+    json!({
+        "title": active.title,
+        "url": active.url,
+        "position_secs": active.position_secs,
+        "duration_secs": active.duration_secs,
+        "feed_title": active.feed_title,
+        "priority": active.priority,
+        "last_played": active.last_played.map(|l| l.to_rfc3339()),
+        "chapter": active.chapter,
+        "chapter_count": active.chapter_count,
+        "chapter_start_secs": active.chapter_start_secs,
+    })
+}
+
+/// The feeds, available and active videos of the database.
+pub fn export(conn: &Connection) -> Result<Value, Error> {
+    Ok(json!({
+        "version": VERSION,
+        "exported": chrono::Local::now().to_rfc3339(),
+        "feeds": iter_feeds(conn)?.iter().map(crate::hooks::feed_json).collect::<Vec<_>>(),
+        "available": iter_available(conn)?
+            .iter()
+            .map(|available| json!({
+                "title": available.title,
+                "url": available.url,
+                "publication": available.publication.to_rfc3339(),
+                "duration_secs": available.duration_secs,
+                "feedurl": available.feed.url,
+            }))
+            .collect::<Vec<_>>(),
+        "active": iter_active(conn, ActiveOrder::Insertion)?
+            .iter()
+            .map(active_json)
+            .collect::<Vec<_>>(),
+    }))
+}
+
+/// Write a backup of the database to the file.
+pub fn export_to_file(conn: &Connection, path: &std::path::Path) -> Result<(), Error> {
+    let backup = export(conn)?;
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer_pretty(file, &backup).map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+fn invalid(what: &str, value: &Value) -> Error {
+    Error::Backup(format!("invalid {}: {}", what, value))
+}
+
+fn string(value: &Value, key: &str) -> Result<String, Error> {
+    value[key]
+        .as_str()
+        .map(|s| s.to_owned())
+        .ok_or_else(|| invalid(key, value))
+}
+
+fn optional_string(value: &Value, key: &str) -> Result<Option<String>, Error> {
+    match &value[key] {
+        Value::Null => Ok(None),
+        _ => string(value, key).map(Some),
+    }
+}
+
+fn optional_date(value: &Value, key: &str) -> Result<Option<DateTime>, Error> {
+    optional_string(value, key)?
+        .map(|d| DateTime::parse_from_rfc3339(&d).map_err(|_| invalid(key, value)))
+        .transpose()
+}
+
+fn optional_f64(value: &Value, key: &str) -> Result<Option<f64>, Error> {
+    match &value[key] {
+        Value::Null => Ok(None),
+        v => v.as_f64().map(Some).ok_or_else(|| invalid(key, value)),
+    }
+}
+
+fn optional_i64(value: &Value, key: &str) -> Result<Option<i64>, Error> {
+    match &value[key] {
+        Value::Null => Ok(None),
+        v => v.as_i64().map(Some).ok_or_else(|| invalid(key, value)),
+    }
+}
+
+fn array<'a>(backup: &'a Value, key: &str) -> Result<&'a Vec<Value>, Error> {
+    backup[key]
+        .as_array()
+        .ok_or_else(|| Error::Backup(format!("missing {}", key)))
+}
+
+fn active_from_json(value: &Value) -> Result<Active, Error> {
+    Ok(Active {
+        title: optional_string(value, "title")?,
+        url: string(value, "url")?,
+        position_secs: optional_f64(value, "position_secs")?.unwrap_or(0.0),
+        duration_secs: optional_f64(value, "duration_secs")?,
+        feed_title: optional_string(value, "feed_title")?,
+        priority: optional_i64(value, "priority")?.unwrap_or(0),
+        last_played: optional_date(value, "last_played")?,
+        chapter: optional_i64(value, "chapter")?,
+        chapter_count: optional_i64(value, "chapter_count")?,
+        chapter_start_secs: optional_f64(value, "chapter_start_secs")?,
+    })
+}
+
+/// Import a backup (see the module documentation). With `replace`, the feeds, available and
+/// active videos of the database are deleted first.
+pub fn import(conn: &Connection, backup: &Value, replace: bool) -> Result<(), Error> {
+    match backup["version"].as_u64() {
+        Some(VERSION) => {}
+        _ => {
+            return Err(Error::Backup(format!(
+                "unsupported version {}",
+                backup["version"]
+            )))
+        }
+    }
+    let feeds = array(backup, "feeds")?;
+    let available = array(backup, "available")?;
+    let active = array(backup, "active")?
+        .iter()
+        .map(active_from_json)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    in_transaction(conn, || {
+        if replace {
+            conn.execute_batch(
+                r#"
+                DELETE FROM active;
+                DELETE FROM available;
+                DELETE FROM available_feed;
+                DELETE FROM feed_category;
+                DELETE FROM seen_entry;
+                DELETE FROM feed;
+                DELETE FROM deleted_item;
+                DELETE FROM playback_lease;
+                "#,
+            )?;
+        }
+        for feed in feeds {
+            let url = string(feed, "url")?;
+            if find_feed(conn, &url)?.is_none() {
+                add_to_feed(
+                    conn,
+                    &Feed {
+                        title: string(feed, "title")?,
+                        url: url.clone(),
+                        lastupdate: None,
+                    },
+                )?;
+            }
+            // Keep the later update so that a refresh does not add old entries again.
+            if let Some(lastupdate) = optional_date(feed, "lastupdate")? {
+                let existing = find_feed(conn, &url)?.and_then(|f| f.lastupdate);
+                if existing.map_or(true, |existing| existing < lastupdate) {
+                    set_lastupdate(conn, &url, &lastupdate)?;
+                }
+            }
+        }
+        for entry in available {
+            let url = string(entry, "url")?;
+            if find_in_active(conn, &url)?.is_some() || active.iter().any(|a| a.url == url) {
+                continue;
+            }
+            let publication = optional_date(entry, "publication")?
+                .ok_or_else(|| invalid("publication", entry))?;
+            ignore_constraint_errors(add_entry_to_available(
+                conn,
+                string(entry, "feedurl")?,
+                &Entry {
+                    title: string(entry, "title")?,
+                    url,
+                    publication,
+                    duration_secs: optional_f64(entry, "duration_secs")?,
+                    short_link: false,
+                    guid: None,
+                    description: None,
+                },
+            ))?;
+        }
+        for imported in &active {
+            match find_in_active(conn, &imported.url)? {
+                Some(existing) => {
+                    if existing.position_secs < imported.position_secs {
+                        set_position_secs(conn, &imported.url, imported.position_secs)?;
+                        if let (Some(chapter), Some(count), Some(start)) = (
+                            imported.chapter,
+                            imported.chapter_count,
+                            imported.chapter_start_secs,
+                        ) {
+                            set_chapter(conn, &imported.url, chapter, count, start)?;
+                        }
+                    }
+                }
+                None => {
+                    add_to_active(conn, imported)?;
+                    remove_from_available(conn, &imported.url)?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Import a backup file written by `export_to_file`.
+pub fn import_from_file(
+    conn: &Connection,
+    path: &std::path::Path,
+    replace: bool,
+) -> Result<(), Error> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let backup: Value = serde_json::from_reader(file)
+        .map_err(|e| Error::Backup(format!("{}: {}", path.display(), e)))?;
+    import(conn, &backup, replace)
+}
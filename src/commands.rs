@@ -0,0 +1,182 @@
+//! Implementations of cli commands that do more than a single database operation, for use by
+//! other frontends.
+use crate::data::{
+    available_for_feed, enqueue_download, find_feed, find_in_active, find_in_available,
+    in_transaction, iter_active, iter_available, make_active, set_title, Available,
+};
+use crate::feeds::normalize_url;
+use crate::{Error, Settings};
+use rusqlite::Connection;
+
+/// Add a video to the active videos unless the `on_add` hook vetoes it. Returns whether the
+/// video has been added.
+pub fn add_video(conn: &Connection, settings: &Settings, url: &str) -> Result<bool, Error> {
+    let url = &normalize_url(url);
+    let response = settings
+        .hooks
+        .on_add(None, serde_json::json!({ "url": url }));
+    if response.veto {
+        return Ok(false);
+    }
+    make_active(conn, url)?;
+    if let Some(title) = response.title {
+        set_title(conn, url, &title)?;
+    }
+    Ok(true)
+}
+
+/// What happened to one of the videos of `add_videos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddResult {
+    Added,
+    AlreadyActive,
+    Vetoed,
+}
+
+/// Add several videos like `add_video` in a single transaction, e.g. an imported "watch later"
+/// list. Returns the normalized url and the result for each of the urls.
+pub fn add_videos(
+    conn: &Connection,
+    settings: &Settings,
+    urls: &[String],
+) -> Result<Vec<(String, AddResult)>, Error> {
+    // The on_add hook runs before the transaction, so that its scripts do not hold the lock of
+    // the database.
+    let mut responses = Vec::new();
+    for url in urls {
+        let url = normalize_url(url);
+        let response = if find_in_active(conn, &url)?.is_some() {
+            None
+        } else {
+            Some(
+                settings
+                    .hooks
+                    .on_add(None, serde_json::json!({ "url": url })),
+            )
+        };
+        responses.push((url, response));
+    }
+    in_transaction(conn, || {
+        let mut results = Vec::new();
+        for (url, response) in responses {
+            let result = match response {
+                // Also urls that are in the list twice.
+                _ if find_in_active(conn, &url)?.is_some() => AddResult::AlreadyActive,
+                None => AddResult::AlreadyActive,
+                Some(response) if response.veto => AddResult::Vetoed,
+                Some(response) => {
+                    make_active(conn, &url)?;
+                    if let Some(title) = response.title {
+                        set_title(conn, &url, &title)?;
+                    }
+                    AddResult::Added
+                }
+            };
+            results.push((url, result));
+        }
+        Ok(results)
+    })
+}
+
+/// Read the urls of a list with one url per line, skipping empty lines and comments (`#`, which
+/// also skips the metadata of m3u playlists).
+pub fn read_url_list(reader: impl std::io::BufRead) -> std::io::Result<Vec<String>> {
+    let mut urls = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            urls.push(line.to_owned());
+        }
+    }
+    Ok(urls)
+}
+
+/// Queue a video for download with the title of the available or active entry, if any.
+pub fn queue_download(conn: &Connection, url: &str) -> Result<(), Error> {
+    let url = &normalize_url(url);
+    let title = match find_in_available(conn, url)? {
+        Some(available) => Some(available.title),
+        None => find_in_active(conn, url)?.and_then(|a| a.title),
+    };
+    enqueue_download(conn, url, title.as_deref())?;
+    Ok(())
+}
+
+/// The available video to watch next: the oldest (or the newest) one, optionally only of the feed
+/// with the given url or title.
+pub fn next_available(
+    conn: &Connection,
+    feed: Option<&str>,
+    newest: bool,
+) -> Result<Option<Available>, Error> {
+    let mut available = match feed {
+        Some(feed) => {
+            if find_feed(conn, feed)?.is_none() {
+                return Err(Error::UnknownFeed(feed.to_owned()));
+            }
+            available_for_feed(conn, feed)?
+        }
+        None => iter_available(conn)?,
+    };
+    // Both lists are sorted newest first.
+    Ok(if newest {
+        available.into_iter().next()
+    } else {
+        available.pop()
+    })
+}
+
+/// Let the user select an active or available video using `picker_command`. Each line passed to
+/// the picker contains the list, title and feed of a video and ends with its url after a tab.
+/// Returns the url of the selected video or `None` if the picker has been cancelled.
+pub fn pick(
+    conn: &Connection,
+    settings: &Settings,
+    active: bool,
+    available: bool,
+) -> Result<Option<String>, Error> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let clean = |s: &str| s.replace('\t', " ").replace('\n', " ");
+    let mut lines = String::new();
+    if active {
+        for a in iter_active(conn, settings.active_order)? {
+            lines.push_str(&format!(
+                "active\t{}\t{}\t{}\n",
+                clean(a.title.as_deref().unwrap_or("Unknown")),
+                clean(a.feed_title.as_deref().unwrap_or("")),
+                a.url
+            ));
+        }
+    }
+    if available {
+        for a in iter_available(conn)? {
+            lines.push_str(&format!(
+                "available\t{}\t{}\t{}\n",
+                clean(&a.title),
+                clean(&a.feed.title),
+                a.url
+            ));
+        }
+    }
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&settings.picker_command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    // The picker may exit before reading everything, e.g. when cancelled early.
+    let _ = child.stdin.take().unwrap().write_all(lines.as_bytes());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit('\t').next())
+        .map(|url| url.trim().to_owned())
+        .filter(|url| !url.is_empty()))
+}
@@ -5,6 +5,17 @@ pub type DateTime = chrono::DateTime<chrono::FixedOffset>; //TODO use UTC, rusql
 fn parse(s: &str) -> chrono::ParseResult<DateTime> {
     DateTime::parse_from_rfc3339(s)
 }
+/// Read a date column. Malformed dates are reported as conversion errors of the column.
+fn get_date(row: &rusqlite::Row, idx: usize) -> Result<DateTime, rusqlite::Error> {
+    let date: String = row.get(idx)?;
+    parse(&date).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+fn get_optional_date(row: &rusqlite::Row, idx: usize) -> Result<Option<DateTime>, rusqlite::Error> {
+    let date: Option<String> = row.get(idx)?;
+    date.map(|_| get_date(row, idx)).transpose()
+}
 fn to_string(d: &DateTime) -> String {
     d.to_rfc3339()
 }
@@ -15,7 +26,15 @@ CREATE TABLE IF NOT EXISTS active (
     title          TEXT,
     position_secs  FLOAT NOT NULL,
     duration_secs  FLOAT,
-    feed_title     TEXT
+    feed_title     TEXT,
+    queue_position INTEGER,
+    priority       INTEGER NOT NULL DEFAULT 0,
+    last_played    TEXT,
+    chapter        INTEGER,
+    chapter_count  INTEGER,
+    chapter_start_secs FLOAT,
+    position_host  TEXT,
+    position_updated TEXT
 );
 "#;
 #[derive(Debug, Clone)]
@@ -25,6 +44,100 @@ pub struct Active {
     pub position_secs: f64,
     pub duration_secs: Option<f64>,
     pub feed_title: Option<String>,
+    pub priority: i64,
+    pub last_played: Option<DateTime>,
+    /// Current chapter (starting at 0), the number of chapters and the start of the chapter.
+    pub chapter: Option<i64>,
+    pub chapter_count: Option<i64>,
+    pub chapter_start_secs: Option<f64>,
+}
+
+/// Where and when the position of an active video has last been saved.
+#[derive(Debug, Clone)]
+pub struct PositionUpdate {
+    pub host: String,
+    pub updated: DateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActiveOrder {
+    Insertion,
+    Priority,
+    LastPlayed,
+    Remaining,
+}
+
+impl ActiveOrder {
+    fn order_by(&self) -> &'static str {
+        // queue_position always comes last so that the order is deterministic.
+        match self {
+            ActiveOrder::Insertion => "queue_position",
+            ActiveOrder::Priority => "priority DESC, queue_position",
+            ActiveOrder::LastPlayed => "last_played IS NULL, last_played DESC, queue_position",
+            ActiveOrder::Remaining => {
+                "duration_secs IS NULL, duration_secs - position_secs, queue_position"
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ActiveOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insertion" => Ok(ActiveOrder::Insertion),
+            "priority" => Ok(ActiveOrder::Priority),
+            "last_played" => Ok(ActiveOrder::LastPlayed),
+            "remaining" => Ok(ActiveOrder::Remaining),
+            o => Err(format!(
+                "Invalid active order '{}' (insertion, priority, last_played or remaining)",
+                o
+            )),
+        }
+    }
+}
+
+/// Order of the videos listed by `query_available` and `query_active`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOrder {
+    /// Newest publication (available) or most recently played (active) first.
+    Date,
+    Title,
+    /// Most watched first. Available videos have not been watched and are ordered by date.
+    Progress,
+}
+
+impl std::str::FromStr for ListOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(ListOrder::Date),
+            "title" => Ok(ListOrder::Title),
+            "progress" => Ok(ListOrder::Progress),
+            o => Err(format!("Invalid order '{}' (date, title or progress)", o)),
+        }
+    }
+}
+
+/// Filters and order of `query_available` and `query_active`.
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    /// Url or title of the feed of the videos.
+    pub feed: Option<String>,
+    /// Only videos published (available) or played (active) since then.
+    pub since: Option<DateTime>,
+    pub limit: Option<usize>,
+    /// The default is the date for available videos and `active_order` for active ones.
+    pub order: Option<ListOrder>,
+}
+
+impl ListQuery {
+    fn limit(&self) -> i64 {
+        // A negative limit means no limit in sqlite.
+        self.limit.map_or(-1, |l| l as i64)
+    }
 }
 
 const TABLE_DEFINITION_AVAILABLE: &'static str = r#"
@@ -33,6 +146,9 @@ CREATE TABLE IF NOT EXISTS available (
     url            TEXT PRIMARY KEY,
     publication    TEXT NOT NULL,
     feedurl        TEXT NOT NULL,
+    duration_secs  FLOAT,
+    guid           TEXT,
+    added          TEXT,
     FOREIGN KEY(feedurl) REFERENCES feed
 );
 "#;
@@ -41,14 +157,25 @@ pub struct Available {
     pub title: String,
     pub url: String,
     pub publication: DateTime,
+    pub duration_secs: Option<f64>,
     pub feed: Feed,
+    /// When the video has been added to the available videos (unknown for videos added by older
+    /// versions).
+    pub added: Option<DateTime>,
 }
 
 const TABLE_DEFINITION_FEED: &'static str = r#"
 CREATE TABLE IF NOT EXISTS feed (
     feedurl         TEXT PRIMARY KEY,
     title           TEXT NOT NULL,
-    lastupdate      Text
+    lastupdate      Text,
+    last_error      TEXT,
+    last_error_time TEXT,
+    auth_user       TEXT,
+    auth_header     TEXT,
+    auth_secret     TEXT,
+    auth_secret_file TEXT,
+    lastfetched     TEXT
 );
 "#;
 
@@ -59,15 +186,338 @@ pub struct Feed {
     pub lastupdate: Option<DateTime>,
 }
 
-pub const TABLE_DEFINITIONS: &[&str] = &[
+/// The error of the last refresh of a feed and since when the feed has been failing.
+#[derive(Debug, Clone)]
+pub struct FeedError {
+    pub message: String,
+    pub since: DateTime,
+}
+
+/// Credentials that are sent when fetching a feed.
+#[derive(Debug, Clone)]
+pub struct FeedAuth {
+    pub kind: AuthKind,
+    pub secret: Secret,
+}
+
+#[derive(Debug, Clone)]
+pub enum AuthKind {
+    /// HTTP basic auth with the user name and the secret as password.
+    Basic { user: String },
+    /// A header with the secret as value, e.g. `Authorization` with `Bearer <token>`.
+    Header { name: String },
+}
+
+/// A password or token, either stored in the database or read from a file on every fetch.
+#[derive(Debug, Clone)]
+pub enum Secret {
+    Value(String),
+    File(String),
+}
+
+impl Secret {
+    pub fn get(&self) -> std::io::Result<String> {
+        match self {
+            Secret::Value(value) => Ok(value.clone()),
+            Secret::File(path) => Ok(std::fs::read_to_string(path)?.trim_end().to_owned()),
+        }
+    }
+}
+
+/// All feeds that have carried a video. A video is only available once (attributed to the feed
+/// it has been seen in first), but the same url may be published by several feeds.
+const TABLE_DEFINITION_AVAILABLE_FEED: &'static str = r#"
+CREATE TABLE IF NOT EXISTS available_feed (
+    url            TEXT NOT NULL,
+    feedurl        TEXT NOT NULL,
+    PRIMARY KEY(url, feedurl),
+    FOREIGN KEY(feedurl) REFERENCES feed
+);
+"#;
+
+/// The ids (see `feeds::Entry::id`) of all entries that have been seen in a feed, including
+/// those that have not been added (e.g., vetoed by a hook) or have been removed since.
+const TABLE_DEFINITION_SEEN_ENTRY: &'static str = r#"
+CREATE TABLE IF NOT EXISTS seen_entry (
+    feedurl        TEXT NOT NULL,
+    id             TEXT NOT NULL,
+    PRIMARY KEY(feedurl, id),
+    FOREIGN KEY(feedurl) REFERENCES feed
+);
+"#;
+
+const TABLE_DEFINITION_FEED_CATEGORY: &'static str = r#"
+CREATE TABLE IF NOT EXISTS feed_category (
+    feedurl         TEXT NOT NULL,
+    category        TEXT NOT NULL,
+    PRIMARY KEY(feedurl, category),
+    FOREIGN KEY(feedurl) REFERENCES feed
+);
+"#;
+
+const TABLE_DEFINITION_DOWNLOAD: &'static str = r#"
+CREATE TABLE IF NOT EXISTS download (
+    url            TEXT PRIMARY KEY,
+    title          TEXT,
+    state          TEXT NOT NULL,
+    path           TEXT,
+    error          TEXT,
+    added          TEXT NOT NULL
+);
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadState {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed,
+}
+
+impl DownloadState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DownloadState::Queued => "queued",
+            DownloadState::Running => "running",
+            DownloadState::Paused => "paused",
+            DownloadState::Done => "done",
+            DownloadState::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for DownloadState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(DownloadState::Queued),
+            "running" => Ok(DownloadState::Running),
+            "paused" => Ok(DownloadState::Paused),
+            "done" => Ok(DownloadState::Done),
+            "failed" => Ok(DownloadState::Failed),
+            o => Err(format!("Invalid download state '{}'", o)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub url: String,
+    pub title: Option<String>,
+    pub state: DownloadState,
+    /// The downloaded file, once the download is done.
+    pub path: Option<String>,
+    pub error: Option<String>,
+    pub added: DateTime,
+}
+
+const TABLE_DEFINITION_FILTER_HISTORY: &'static str = r#"
+CREATE TABLE IF NOT EXISTS filter_history (
+    filter         TEXT PRIMARY KEY,
+    used           TEXT NOT NULL
+);
+"#;
+
+/// Videos removed from the active or available list, so that they can be restored later. Only the
+/// columns of the respective list are set.
+const TABLE_DEFINITION_DELETED_ITEM: &'static str = r#"
+CREATE TABLE IF NOT EXISTS deleted_item (
+    id             INTEGER PRIMARY KEY,
+    list           TEXT NOT NULL,
+    deleted        TEXT NOT NULL,
+    url            TEXT NOT NULL,
+    title          TEXT,
+    duration_secs  FLOAT,
+    position_secs  FLOAT,
+    feed_title     TEXT,
+    priority       INTEGER,
+    last_played    TEXT,
+    chapter        INTEGER,
+    chapter_count  INTEGER,
+    chapter_start_secs FLOAT,
+    publication    TEXT,
+    feedurl        TEXT
+);
+"#;
+
+#[derive(Debug, Clone)]
+pub enum DeletedItem {
+    Active(Active),
+    Available(Available),
+}
+
+impl DeletedItem {
+    pub fn url(&self) -> &str {
+        match self {
+            DeletedItem::Active(a) => &a.url,
+            DeletedItem::Available(a) => &a.url,
+        }
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            DeletedItem::Active(a) => a.title.as_deref(),
+            DeletedItem::Available(a) => Some(&a.title),
+        }
+    }
+
+    /// The name of the list the item has been removed from.
+    pub fn list(&self) -> &'static str {
+        match self {
+            DeletedItem::Active(_) => "active",
+            DeletedItem::Available(_) => "available",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaybackLease {
+    pub url: String,
+    /// Describes the instance that plays the video, e.g. "host (pid 1234)".
+    pub holder: String,
+    pub expires: DateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct Deleted {
+    pub id: i64,
+    pub deleted: DateTime,
+    pub item: DeletedItem,
+}
+
+/// Values that uvp remembers between runs, e.g., the start of the last tui session.
+const TABLE_DEFINITION_STATE: &'static str = r#"
+CREATE TABLE IF NOT EXISTS state (
+    key            TEXT PRIMARY KEY,
+    value          TEXT NOT NULL
+);
+"#;
+
+/// Videos that are being played, so that other instances sharing the database do not play them
+/// at the same time. Leases that have expired are ignored.
+const TABLE_DEFINITION_PLAYBACK_LEASE: &'static str = r#"
+CREATE TABLE IF NOT EXISTS playback_lease (
+    url            TEXT PRIMARY KEY,
+    holder         TEXT NOT NULL,
+    expires        TEXT NOT NULL
+);
+"#;
+
+const TABLE_DEFINITIONS: &[&str] = &[
     TABLE_DEFINITION_FEED,
+    TABLE_DEFINITION_FEED_CATEGORY,
     TABLE_DEFINITION_AVAILABLE,
+    TABLE_DEFINITION_AVAILABLE_FEED,
+    TABLE_DEFINITION_SEEN_ENTRY,
     TABLE_DEFINITION_ACTIVE,
+    TABLE_DEFINITION_DOWNLOAD,
+    TABLE_DEFINITION_FILTER_HISTORY,
+    TABLE_DEFINITION_DELETED_ITEM,
+    TABLE_DEFINITION_STATE,
+    TABLE_DEFINITION_PLAYBACK_LEASE,
+];
+
+/// Indexes for the common queries, created after the migrations. The primary keys (e.g.,
+/// `active.url`) are indexed by sqlite anyway.
+const INDEX_DEFINITIONS: &str = r#"
+CREATE INDEX IF NOT EXISTS available_publication ON available(publication);
+CREATE INDEX IF NOT EXISTS available_feedurl ON available(feedurl);
+"#;
+
+/// Schema changes for databases created by older versions of uvp. The database's user_version
+/// is the number of migrations that have already been applied. The table definitions above
+/// always describe the current schema, so new databases skip all migrations.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    ALTER TABLE active ADD COLUMN queue_position INTEGER;
+    ALTER TABLE active ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE active ADD COLUMN last_played TEXT;
+    UPDATE active SET queue_position = rowid;
+    "#,
+    r#"
+    ALTER TABLE available ADD COLUMN duration_secs FLOAT;
+    "#,
+    r#"
+    ALTER TABLE active ADD COLUMN chapter INTEGER;
+    ALTER TABLE active ADD COLUMN chapter_count INTEGER;
+    ALTER TABLE active ADD COLUMN chapter_start_secs FLOAT;
+    "#,
+    r#"
+    ALTER TABLE feed ADD COLUMN last_error TEXT;
+    ALTER TABLE feed ADD COLUMN last_error_time TEXT;
+    "#,
+    r#"
+    ALTER TABLE available ADD COLUMN guid TEXT;
+    "#,
+    r#"
+    ALTER TABLE available ADD COLUMN added TEXT;
+    "#,
+    r#"
+    ALTER TABLE feed ADD COLUMN auth_user TEXT;
+    ALTER TABLE feed ADD COLUMN auth_header TEXT;
+    ALTER TABLE feed ADD COLUMN auth_secret TEXT;
+    ALTER TABLE feed ADD COLUMN auth_secret_file TEXT;
+    "#,
+    r#"
+    ALTER TABLE feed ADD COLUMN lastfetched TEXT;
+    "#,
+    r#"
+    ALTER TABLE active ADD COLUMN position_host TEXT;
+    ALTER TABLE active ADD COLUMN position_updated TEXT;
+    "#,
 ];
 
+/// Enough to keep all statements of this module prepared.
+const STATEMENT_CACHE_CAPACITY: usize = 96;
+
+pub fn init_db(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    let new_db: bool = conn.query_row(
+        "SELECT count(*) = 0 FROM sqlite_master WHERE type = 'table' AND name = 'feed'",
+        params![],
+        |row| row.get(0),
+    )?;
+    for def in TABLE_DEFINITIONS {
+        conn.execute_batch(def)?;
+    }
+    let version: i64 = if new_db {
+        MIGRATIONS.len() as i64
+    } else {
+        let version: i64 = conn.query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+        for migration in MIGRATIONS.iter().skip(version as usize) {
+            conn.execute_batch(migration)?;
+        }
+        MIGRATIONS.len() as i64
+    };
+    conn.execute_batch(INDEX_DEFINITIONS)?;
+    conn.execute_batch(&format!("PRAGMA user_version = {}", version))?;
+    Ok(())
+}
+
+/// Run `f` in a transaction that is committed if `f` succeeds and rolled back otherwise.
+pub fn in_transaction<T, E: From<rusqlite::Error>>(
+    conn: &Connection,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    conn.execute_batch("BEGIN")?;
+    match f() {
+        Ok(res) => {
+            conn.execute_batch("COMMIT")?;
+            Ok(res)
+        }
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK");
+            Err(e)
+        }
+    }
+}
+
 /// Feed -----------------------------------------------------------------------
 pub fn iter_feeds(conn: &Connection) -> Result<Vec<Feed>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         r#"
         SELECT feedurl, title, lastupdate FROM feed
         "#,
@@ -77,99 +527,332 @@ pub fn iter_feeds(conn: &Connection) -> Result<Vec<Feed>, rusqlite::Error> {
             Ok(Feed {
                 url: row.get(0)?,
                 title: row.get(1)?,
-                lastupdate: row.get(2).map(|lastupdate: Option<String>| {
-                    lastupdate.map(|lastupdate| parse(&lastupdate).unwrap())
-                })?,
+                lastupdate: get_optional_date(row, 2)?,
             })
         })?
         .collect::<Result<Vec<_>, rusqlite::Error>>();
     res
 }
+pub fn find_feed(conn: &Connection, url_or_title: &str) -> Result<Option<Feed>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT feedurl, title, lastupdate FROM feed
+        WHERE feedurl = ?1 OR title = ?1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url_or_title), |row| {
+        Ok(Feed {
+            url: row.get(0)?,
+            title: row.get(1)?,
+            lastupdate: get_optional_date(row, 2)?,
+        })
+    })?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
 pub fn add_to_feed(conn: &Connection, feed: &Feed) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
         INSERT INTO feed (title, feedurl) VALUES (?1, ?2)
         "#,
-        params!(feed.title, feed.url),
+    )?
+    .execute(params!(feed.title, feed.url))?;
+    Ok(())
+}
+pub fn set_lastupdate(
+    conn: &Connection,
+    url: &str,
+    lastupdate: &DateTime,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE feed SET lastupdate = ?1 WHERE feedurl = ?2
+        "#,
+    )?
+    .execute(params!(to_string(lastupdate), url))?;
+    Ok(())
+}
+/// Record that the feed has been fetched successfully.
+pub fn set_lastfetched(
+    conn: &Connection,
+    url: &str,
+    lastfetched: &DateTime,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE feed SET lastfetched = ?1 WHERE feedurl = ?2
+        "#,
+    )?
+    .execute(params!(to_string(lastfetched), url))?;
+    Ok(())
+}
+pub fn lastfetched(conn: &Connection, url: &str) -> Result<Option<DateTime>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT lastfetched FROM feed WHERE feedurl = ?1
+        "#,
     )?;
+    let res = stmt.query_map(params!(url), |row| get_optional_date(row, 0))?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?.flatten())
+}
+/// Record the error of a refresh of the feed or, with `None`, that the refresh succeeded.
+pub fn set_feed_error(
+    conn: &Connection,
+    url: &str,
+    error: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE feed SET last_error = ?2,
+            last_error_time = CASE WHEN ?2 IS NULL THEN NULL ELSE IFNULL(last_error_time, ?3) END
+        WHERE feedurl = ?1
+        "#,
+    )?
+    .execute(params!(url, error, to_string(&chrono::Local::now().into())))?;
     Ok(())
 }
-pub fn remove_feed(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
-    conn.execute(
+pub fn feed_error(conn: &Connection, url: &str) -> Result<Option<FeedError>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT last_error, last_error_time FROM feed
+        WHERE feedurl = ?1 AND last_error IS NOT NULL
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url), |row| {
+        Ok(FeedError {
+            message: row.get(0)?,
+            since: get_date(row, 1)?,
+        })
+    })?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+/// Store the credentials for fetching the feed or, with `None`, remove them.
+pub fn set_feed_auth(
+    conn: &Connection,
+    url: &str,
+    auth: Option<&FeedAuth>,
+) -> Result<(), rusqlite::Error> {
+    let (user, header) = match auth.map(|a| &a.kind) {
+        Some(AuthKind::Basic { user }) => (Some(user), None),
+        Some(AuthKind::Header { name }) => (None, Some(name)),
+        None => (None, None),
+    };
+    let (secret, secret_file) = match auth.map(|a| &a.secret) {
+        Some(Secret::Value(value)) => (Some(value), None),
+        Some(Secret::File(path)) => (None, Some(path)),
+        None => (None, None),
+    };
+    conn.prepare_cached(
+        r#"
+        UPDATE feed SET auth_user = ?2, auth_header = ?3, auth_secret = ?4, auth_secret_file = ?5
+        WHERE feedurl = ?1
+        "#,
+    )?
+    .execute(params!(url, user, header, secret, secret_file))?;
+    Ok(())
+}
+pub fn feed_auth(conn: &Connection, url: &str) -> Result<Option<FeedAuth>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
         r#"
-        DELETE FROM feed WHERE feedurl = ?1
+        SELECT auth_user, auth_header, auth_secret, auth_secret_file FROM feed
+        WHERE feedurl = ?1
         "#,
-        params!(url),
     )?;
+    let res = stmt.query_map(params!(url), |row| {
+        let kind = match (row.get(0)?, row.get(1)?) {
+            (Some(user), _) => AuthKind::Basic { user },
+            (None, Some(name)) => AuthKind::Header { name },
+            (None, None) => return Ok(None),
+        };
+        let secret = match (row.get::<_, Option<String>>(2)?, row.get(3)?) {
+            (_, Some(path)) => Secret::File(path),
+            (value, None) => Secret::Value(value.unwrap_or_default()),
+        };
+        Ok(Some(FeedAuth { kind, secret }))
+    })?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?.flatten())
+}
+/// Remove the feed and everything that refers to it. Available videos of the feed that have also
+/// been published by another feed are attributed to that feed instead.
+pub fn remove_feed(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
+    in_transaction(conn, || {
+        conn.prepare_cached(
+            r#"
+            UPDATE available SET feedurl = (
+                SELECT feedurl FROM available_feed
+                WHERE available_feed.url = available.url AND feedurl != ?1
+            )
+            WHERE feedurl = ?1 AND EXISTS (
+                SELECT * FROM available_feed
+                WHERE available_feed.url = available.url AND feedurl != ?1
+            )
+            "#,
+        )?
+        .execute(params!(url))?;
+        for table in &[
+            "available",
+            "available_feed",
+            "feed_category",
+            "seen_entry",
+            "feed",
+        ] {
+            conn.prepare_cached(&format!("DELETE FROM {} WHERE feedurl = ?1", table))?
+                .execute(params!(url))?;
+        }
+        Ok(())
+    })
+}
+/// Delete the rows that refer to feeds that do not exist (anymore), e.g., left behind by older
+/// versions that did not clean up when removing a feed. Returns the number of deleted rows.
+pub fn remove_orphans(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    in_transaction(conn, || {
+        let mut deleted = 0;
+        for table in &["available", "available_feed", "feed_category", "seen_entry"] {
+            deleted += conn
+                .prepare_cached(&format!(
+                    "DELETE FROM {} WHERE feedurl NOT IN (SELECT feedurl FROM feed)",
+                    table
+                ))?
+                .execute(params!())?;
+        }
+        Ok(deleted)
+    })
+}
+pub fn add_category(conn: &Connection, url: &str, category: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO feed_category (feedurl, category) VALUES (?1, ?2)
+        "#,
+    )?
+    .execute(params!(url, category))?;
     Ok(())
 }
+pub fn categories_of_feed(conn: &Connection, url: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT category FROM feed_category WHERE feedurl = ?1 ORDER BY category
+        "#,
+    )?;
+    let res = stmt
+        .query_map(params!(url), |row| row.get(0))?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
 
 /// Available ------------------------------------------------------------------
+fn available_from_row(row: &rusqlite::Row) -> Result<Available, rusqlite::Error> {
+    Ok(Available {
+        title: row.get(0)?,
+        url: row.get(1)?,
+        publication: get_date(row, 2)?,
+        feed: Feed {
+            url: row.get(3)?,
+            title: row.get(4)?,
+            lastupdate: get_optional_date(row, 5)?,
+        },
+        duration_secs: row.get(6)?,
+        added: get_optional_date(row, 7)?,
+    })
+}
+
+/// All available videos, newest first. The feeds are read once instead of joining them to every
+/// row, which matters for large backlogs that are read on every tui update.
 pub fn iter_available(conn: &Connection) -> Result<Vec<Available>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
+    let feeds = iter_feeds(conn)?
+        .into_iter()
+        .map(|feed| (feed.url.clone(), feed))
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut stmt = conn.prepare_cached(
         r#"
-        SELECT available.title, url, publication, feedurl, feed.title, lastupdate
-        FROM available INNER JOIN feed USING(feedurl)
+        SELECT title, url, publication, feedurl, duration_secs, added FROM available
         ORDER BY publication DESC
         "#,
     )?;
-    let res = stmt
-        .query_map(params!(), |row| {
-            let publication: String = row.get(2)?;
-            Ok(Available {
+    let mut res = Vec::new();
+    let mut rows = stmt.query(params!())?;
+    while let Some(row) = rows.next()? {
+        let feedurl: String = row.get(3)?;
+        // Like the join in the other queries, skip entries of feeds that do not exist (anymore).
+        if let Some(feed) = feeds.get(&feedurl) {
+            res.push(Available {
                 title: row.get(0)?,
                 url: row.get(1)?,
-                publication: parse(&publication).unwrap(),
-                feed: Feed {
-                    url: row.get(3)?,
-                    title: row.get(4)?,
-                    lastupdate: row.get(5).map(|lastupdate: Option<String>| {
-                        lastupdate.map(|lastupdate| parse(&lastupdate).unwrap())
-                    })?,
-                },
-            })
-        })?
+                publication: get_date(row, 2)?,
+                duration_secs: row.get(4)?,
+                feed: feed.clone(),
+                added: get_optional_date(row, 5)?,
+            });
+        }
+    }
+    Ok(res)
+}
+
+pub fn available_for_feed(
+    conn: &Connection,
+    feed_url_or_title: &str,
+) -> Result<Vec<Available>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT available.title, url, publication, feedurl, feed.title, lastupdate,
+            duration_secs, added
+        FROM available INNER JOIN feed USING(feedurl)
+        WHERE feedurl = ?1 OR feed.title = ?1
+        ORDER BY publication DESC
+        "#,
+    )?;
+    let res = stmt
+        .query_map(params!(feed_url_or_title), available_from_row)?
         .collect::<Result<Vec<_>, rusqlite::Error>>();
     res
 }
 
+/// A random available video, optionally only of the feed with the given url or title.
+pub fn random_available(
+    conn: &Connection,
+    feed_url_or_title: Option<&str>,
+) -> Result<Option<Available>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT available.title, url, publication, feedurl, feed.title, lastupdate,
+            duration_secs, added
+        FROM available INNER JOIN feed USING(feedurl)
+        WHERE ?1 IS NULL OR feedurl = ?1 OR feed.title = ?1
+        ORDER BY RANDOM()
+        LIMIT 1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(feed_url_or_title), available_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
 pub fn find_in_available(
     conn: &Connection,
     url: &str,
 ) -> Result<Option<Available>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare_cached(
         r#"
-        SELECT available.title, url, publication, feedurl, feed.title, lastupdate
+        SELECT available.title, url, publication, feedurl, feed.title, lastupdate,
+            duration_secs, added
         FROM available INNER JOIN feed USING(feedurl)
         WHERE url = ?1
         "#,
     )?;
-    let res = stmt.query_map(params!(url), |row| {
-        let publication: String = row.get(2)?;
-        Ok(Available {
-            title: row.get(0)?,
-            url: row.get(1)?,
-            publication: parse(&publication).unwrap(),
-            feed: Feed {
-                url: row.get(3)?,
-                title: row.get(4)?,
-                lastupdate: row.get(5).map(|lastupdate: Option<String>| {
-                    lastupdate.map(|lastupdate| parse(&lastupdate).unwrap())
-                })?,
-            },
-        })
-    })?;
+    let res = stmt.query_map(params!(url), available_from_row)?;
     let mut iter = res.into_iter();
     Ok(iter.next().transpose()?)
 }
 
 pub fn remove_from_available(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
         DELETE FROM available WHERE url = ?1
         "#,
-        params!(url),
-    )?;
+    )?
+    .execute(params!(url))?;
     Ok(())
 }
 
@@ -178,161 +861,983 @@ pub fn add_entry_to_available(
     feed: String,
     available: &crate::feeds::Entry,
 ) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
-        INSERT INTO available (title, url, feedurl, publication) VALUES (?1, ?2, ?3, ?4)
+        INSERT INTO available (title, url, feedurl, publication, duration_secs, guid, added)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
         "#,
-        params!(
-            available.title,
-            available.url,
-            feed,
-            to_string(&available.publication)
-        ),
-    )?;
+    )?
+    .execute(params!(
+        available.title,
+        available.url,
+        feed,
+        to_string(&available.publication),
+        available.duration_secs,
+        available.guid,
+        to_string(&chrono::Local::now().into())
+    ))?;
     Ok(())
 }
 
-pub fn add_to_available(conn: &Connection, available: &Available) -> Result<(), rusqlite::Error> {
-    conn.execute(
+/// Record that the feed has carried the video.
+pub fn add_feed_of_entry(
+    conn: &Connection,
+    url: &str,
+    feedurl: &str,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
         r#"
-        INSERT INTO available (title, url, feedurl, publication) VALUES (?1, ?2, ?3, ?4)
+        INSERT OR IGNORE INTO available_feed (url, feedurl) VALUES (?1, ?2)
         "#,
-        params!(
-            available.title,
-            available.url,
-            available.feed.url,
-            to_string(&available.publication)
-        ),
-    )?;
+    )?
+    .execute(params!(url, feedurl))?;
     Ok(())
 }
 
-/// Active ---------------------------------------------------------------------
-
-pub fn iter_active(conn: &Connection) -> Result<Vec<Active>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
+/// All feeds that have carried the video, including the one it is available from.
+pub fn feeds_of_entry(conn: &Connection, url: &str) -> Result<Vec<Feed>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
         r#"
-        SELECT title, url, position_secs, duration_secs, feed_title
-        FROM active
+        SELECT feedurl, title, lastupdate FROM feed
+        WHERE feedurl IN (
+            SELECT feedurl FROM available_feed WHERE url = ?1
+            UNION SELECT feedurl FROM available WHERE url = ?1
+        )
+        ORDER BY title
         "#,
     )?;
     let res = stmt
-        .query_map(params!(), |row| {
-            Ok(Active {
-                title: row.get(0)?,
-                url: row.get(1)?,
-                position_secs: row.get(2)?,
-                duration_secs: row.get(3)?,
-                feed_title: row.get(4)?,
+        .query_map(params!(url), |row| {
+            Ok(Feed {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                lastupdate: get_optional_date(row, 2)?,
             })
         })?
         .collect::<Result<Vec<_>, rusqlite::Error>>();
     res
 }
 
-pub fn find_in_active(conn: &Connection, url: &str) -> Result<Option<Active>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
+/// Update the available entry of the feed with the guid of `entry`, e.g., after the entry has
+/// been edited in the feed. The url is kept, since downloads, removed videos and the feeds of the
+/// video refer to it.
+pub fn update_available_entry(
+    conn: &Connection,
+    feed: &str,
+    entry: &crate::feeds::Entry,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
         r#"
-        SELECT title, url, position_secs, duration_secs, feed_title
-        FROM active
-        where url = ?1
+        UPDATE available SET title = ?3, publication = ?4,
+            duration_secs = coalesce(?5, duration_secs)
+        WHERE feedurl = ?1 AND guid = ?2
         "#,
-    )?;
-    let res = stmt.query_map(params!(url), |row| {
-        Ok(Active {
-            title: row.get(0)?,
-            url: row.get(1)?,
-            position_secs: row.get(2)?,
-            duration_secs: row.get(3)?,
-            feed_title: row.get(4)?,
-        })
-    })?;
-    let mut iter = res.into_iter();
-    Ok(iter.next().transpose()?)
+    )?
+    .execute(params!(
+        feed,
+        entry.guid,
+        entry.title,
+        to_string(&entry.publication),
+        entry.duration_secs
+    ))?;
+    Ok(())
 }
 
-pub fn add_to_active(conn: &Connection, active: &Active) -> Result<(), rusqlite::Error> {
-    conn.execute(
+/// Urls of the newest available videos whose duration is unknown.
+pub fn available_without_duration(
+    conn: &Connection,
+    limit: usize,
+) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
         r#"
-        INSERT INTO active (url, title, position_secs, feed_title) VALUES (?1, ?2, ?3, ?4)
+        SELECT url FROM available WHERE duration_secs IS NULL
+        ORDER BY publication DESC
+        LIMIT ?1
         "#,
-        params!(
-            active.url,
-            active.title,
-            active.position_secs,
-            active.feed_title
-        ),
     )?;
-    Ok(())
+    let res = stmt.query_map(params!(limit as i64), |row| row.get(0))?;
+    res.collect()
 }
 
-pub fn make_active(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
-    if let Some(available) = find_in_available(&conn, url)? {
-        add_to_active(
-            &conn,
-            &Active {
-                url: url.to_owned(),
-                title: Some(available.title),
-                position_secs: 0.0,
-                duration_secs: None,
-                feed_title: Some(available.feed.title),
-            },
-        )?;
-        remove_from_available(&conn, url)
-    } else {
-        add_to_active(
-            &conn,
-            &Active {
-                url: url.to_owned(),
-                title: None,
-                position_secs: 0.0,
-                duration_secs: None,
-                feed_title: None,
-            },
+pub fn set_available_duration(
+    conn: &Connection,
+    url: &str,
+    duration_secs: f64,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE available SET duration_secs = ?2 WHERE url = ?1
+        "#,
+    )?
+    .execute(params!(url, duration_secs))?;
+    Ok(())
+}
+
+pub fn is_seen(conn: &Connection, feed: &str, id: &str) -> Result<bool, rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        SELECT count(*) > 0 FROM seen_entry WHERE feedurl = ?1 AND id = ?2
+        "#,
+    )?
+    .query_row(params!(feed, id), |row| row.get(0))
+}
+
+/// Whether any entries have been seen in the feed, which is not the case for feeds of databases
+/// created before entries were tracked.
+pub fn has_seen_entries(conn: &Connection, feed: &str) -> Result<bool, rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        SELECT count(*) > 0 FROM seen_entry WHERE feedurl = ?1
+        "#,
+    )?
+    .query_row(params!(feed), |row| row.get(0))
+}
+
+/// Forget the seen entries and the last update of the feed, so that the next refresh adds all
+/// entries of the feed again.
+pub fn forget_entries(conn: &Connection, feed: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        DELETE FROM seen_entry WHERE feedurl = ?1
+        "#,
+    )?
+    .execute(params!(feed))?;
+    conn.prepare_cached(
+        r#"
+        UPDATE feed SET lastupdate = NULL WHERE feedurl = ?1
+        "#,
+    )?
+    .execute(params!(feed))?;
+    Ok(())
+}
+
+pub fn mark_seen(conn: &Connection, feed: &str, id: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO seen_entry (feedurl, id) VALUES (?1, ?2)
+        "#,
+    )?
+    .execute(params!(feed, id))?;
+    Ok(())
+}
+
+pub fn add_to_available(conn: &Connection, available: &Available) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT INTO available (title, url, feedurl, publication, duration_secs, added)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        "#,
+    )?
+    .execute(params!(
+        available.title,
+        available.url,
+        available.feed.url,
+        to_string(&available.publication),
+        available.duration_secs,
+        available.added.as_ref().map(to_string)
+    ))?;
+    Ok(())
+}
+
+/// Active ---------------------------------------------------------------------
+
+fn active_from_row(row: &rusqlite::Row) -> Result<Active, rusqlite::Error> {
+    Ok(Active {
+        title: row.get(0)?,
+        url: row.get(1)?,
+        position_secs: row.get(2)?,
+        duration_secs: row.get(3)?,
+        feed_title: row.get(4)?,
+        priority: row.get(5)?,
+        last_played: get_optional_date(row, 6)?,
+        chapter: row.get(7)?,
+        chapter_count: row.get(8)?,
+        chapter_start_secs: row.get(9)?,
+    })
+}
+
+/// The available videos that match the query.
+pub fn query_available(
+    conn: &Connection,
+    query: &ListQuery,
+) -> Result<Vec<Available>, rusqlite::Error> {
+    let order_by = match query.order {
+        Some(ListOrder::Title) => "available.title COLLATE NOCASE, publication DESC",
+        Some(ListOrder::Date) | Some(ListOrder::Progress) | None => "publication DESC",
+    };
+    let mut stmt = conn.prepare_cached(&format!(
+        r#"
+        SELECT available.title, url, publication, feedurl, feed.title, lastupdate,
+            duration_secs, added
+        FROM available INNER JOIN feed USING(feedurl)
+        WHERE (?1 IS NULL OR feedurl = ?1 OR feed.title = ?1)
+            AND (?2 IS NULL OR julianday(publication) >= julianday(?2))
+        ORDER BY {}
+        LIMIT ?3
+        "#,
+        order_by
+    ))?;
+    let res = stmt
+        .query_map(
+            params!(
+                query.feed,
+                query.since.as_ref().map(to_string),
+                query.limit()
+            ),
+            available_from_row,
+        )?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
+/// The active videos that match the query.
+pub fn query_active(
+    conn: &Connection,
+    query: &ListQuery,
+    default_order: ActiveOrder,
+) -> Result<Vec<Active>, rusqlite::Error> {
+    let order_by = match query.order {
+        Some(ListOrder::Date) => ActiveOrder::LastPlayed.order_by(),
+        Some(ListOrder::Title) => "title IS NULL, title COLLATE NOCASE, queue_position",
+        Some(ListOrder::Progress) => {
+            "duration_secs IS NULL, position_secs / duration_secs DESC, queue_position"
+        }
+        None => default_order.order_by(),
+    };
+    let mut stmt = conn.prepare_cached(&format!(
+        r#"
+        SELECT title, url, position_secs, duration_secs, feed_title, priority, last_played,
+            chapter, chapter_count, chapter_start_secs
+        FROM active
+        WHERE (?1 IS NULL OR feed_title = ?1
+                OR feed_title IN (SELECT title FROM feed WHERE feedurl = ?1))
+            AND (?2 IS NULL OR julianday(last_played) >= julianday(?2))
+        ORDER BY {}
+        LIMIT ?3
+        "#,
+        order_by
+    ))?;
+    let res = stmt
+        .query_map(
+            params!(
+                query.feed,
+                query.since.as_ref().map(to_string),
+                query.limit()
+            ),
+            active_from_row,
+        )?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
+pub fn iter_active(conn: &Connection, order: ActiveOrder) -> Result<Vec<Active>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        r#"
+        SELECT title, url, position_secs, duration_secs, feed_title, priority, last_played,
+            chapter, chapter_count, chapter_start_secs
+        FROM active
+        ORDER BY {}
+        "#,
+        order.order_by()
+    ))?;
+    let res = stmt
+        .query_map(params!(), active_from_row)?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
+
+pub fn find_in_active(conn: &Connection, url: &str) -> Result<Option<Active>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT title, url, position_secs, duration_secs, feed_title, priority, last_played,
+            chapter, chapter_count, chapter_start_secs
+        FROM active
+        where url = ?1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url), active_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// The active video that has been played last, if any has been played.
+pub fn most_recently_played(conn: &Connection) -> Result<Option<Active>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT title, url, position_secs, duration_secs, feed_title, priority, last_played,
+            chapter, chapter_count, chapter_start_secs
+        FROM active
+        WHERE last_played IS NOT NULL
+        ORDER BY last_played DESC
+        LIMIT 1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(), active_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+pub fn add_to_active(conn: &Connection, active: &Active) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT INTO active (
+            url, title, position_secs, duration_secs, feed_title, priority, last_played,
+            chapter, chapter_count, chapter_start_secs, queue_position
         )
-    }
+        VALUES (
+            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+            (SELECT IFNULL(MAX(queue_position), 0) + 1 FROM active)
+        )
+        "#,
+    )?
+    .execute(params!(
+        active.url,
+        active.title,
+        active.position_secs,
+        active.duration_secs,
+        active.feed_title,
+        active.priority,
+        active.last_played.as_ref().map(to_string),
+        active.chapter,
+        active.chapter_count,
+        active.chapter_start_secs
+    ))?;
+    Ok(())
+}
+
+/// Move the active video to the start of the queue (see `ActiveOrder::Insertion`).
+pub fn move_to_front(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE active SET queue_position = (SELECT IFNULL(MIN(queue_position), 1) - 1 FROM active)
+        WHERE url = ?1
+        "#,
+    )?
+    .execute(params!(url))?;
+    Ok(())
+}
+
+/// The entry that `make_active` would add to the list of active videos.
+pub fn new_active(conn: &Connection, url: &str) -> Result<Active, rusqlite::Error> {
+    Ok(if let Some(available) = find_in_available(&conn, url)? {
+        Active {
+            url: url.to_owned(),
+            title: Some(available.title),
+            position_secs: 0.0,
+            duration_secs: available.duration_secs,
+            feed_title: Some(available.feed.title),
+            priority: 0,
+            last_played: None,
+            chapter: None,
+            chapter_count: None,
+            chapter_start_secs: None,
+        }
+    } else {
+        Active {
+            url: url.to_owned(),
+            title: None,
+            position_secs: 0.0,
+            duration_secs: None,
+            feed_title: None,
+            priority: 0,
+            last_played: None,
+            chapter: None,
+            chapter_count: None,
+            chapter_start_secs: None,
+        }
+    })
+}
+
+pub fn make_active(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
+    add_to_active(&conn, &new_active(conn, url)?)?;
+    remove_from_available(&conn, url)
 }
 pub fn set_position_secs(
     conn: &Connection,
     url: &str,
     position_secs: f64,
 ) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
-        UPDATE active SET position_secs = ?1 WHERE url = ?2
+        UPDATE active SET position_secs = ?1, position_host = ?3, position_updated = ?4
+        WHERE url = ?2
         "#,
-        params!(position_secs, url),
-    )?;
+    )?
+    .execute(params!(
+        position_secs,
+        url,
+        crate::hostname(),
+        to_string(&chrono::Local::now().into())
+    ))?;
     Ok(())
 }
+
+pub fn position_update(
+    conn: &Connection,
+    url: &str,
+) -> Result<Option<PositionUpdate>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT position_host, position_updated FROM active
+        WHERE url = ?1 AND position_host IS NOT NULL AND position_updated IS NOT NULL
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url), |row| {
+        Ok(PositionUpdate {
+            host: row.get(0)?,
+            updated: get_date(row, 1)?,
+        })
+    })?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
 pub fn set_duration(
     conn: &Connection,
     url: &str,
     duration_secs: f64,
 ) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
         UPDATE active SET duration_secs = ?1 WHERE url = ?2
         "#,
-        params!(duration_secs, url),
-    )?;
+    )?
+    .execute(params!(duration_secs, url))?;
+    Ok(())
+}
+pub fn set_priority(conn: &Connection, url: &str, priority: i64) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE active SET priority = ?1 WHERE url = ?2
+        "#,
+    )?
+    .execute(params!(priority, url))?;
     Ok(())
 }
+pub fn set_last_played(
+    conn: &Connection,
+    url: &str,
+    last_played: &DateTime,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE active SET last_played = ?1 WHERE url = ?2
+        "#,
+    )?
+    .execute(params!(to_string(last_played), url))?;
+    Ok(())
+}
+pub fn set_chapter(
+    conn: &Connection,
+    url: &str,
+    chapter: i64,
+    chapter_count: i64,
+    chapter_start_secs: f64,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE active SET chapter = ?1, chapter_count = ?2, chapter_start_secs = ?3
+        WHERE url = ?4
+        "#,
+    )?
+    .execute(params!(chapter, chapter_count, chapter_start_secs, url))?;
+    Ok(())
+}
+
 pub fn set_title(conn: &Connection, url: &str, title: &str) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
         UPDATE active SET title = ?1 WHERE url = ?2
         "#,
-        params!(title, url),
-    )?;
+    )?
+    .execute(params!(title, url))?;
     Ok(())
 }
 pub fn remove_from_active(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
-    conn.execute(
+    conn.prepare_cached(
         r#"
         DELETE FROM active WHERE url = ?1
         "#,
-        params!(url),
+    )?
+    .execute(params!(url))?;
+    Ok(())
+}
+
+/// Download -------------------------------------------------------------------
+
+fn download_from_row(row: &rusqlite::Row) -> Result<Download, rusqlite::Error> {
+    let state: String = row.get(2)?;
+    Ok(Download {
+        url: row.get(0)?,
+        title: row.get(1)?,
+        state: state.parse().map_err(|e: String| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, e.into())
+        })?,
+        path: row.get(3)?,
+        error: row.get(4)?,
+        added: get_date(row, 5)?,
+    })
+}
+
+pub fn iter_downloads(conn: &Connection) -> Result<Vec<Download>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT url, title, state, path, error, added FROM download
+        ORDER BY added
+        "#,
+    )?;
+    let res = stmt
+        .query_map(params!(), download_from_row)?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
+
+pub fn find_download(conn: &Connection, url: &str) -> Result<Option<Download>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT url, title, state, path, error, added FROM download
+        WHERE url = ?1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url), download_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// Queue the video for download. Failed and paused downloads are queued again, others are left
+/// as they are.
+pub fn enqueue_download(
+    conn: &Connection,
+    url: &str,
+    title: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO download (url, title, state, added) VALUES (?1, ?2, ?3, ?4)
+        "#,
+    )?
+    .execute(params!(
+        url,
+        title,
+        DownloadState::Queued.as_str(),
+        to_string(&chrono::Local::now().into())
+    ))?;
+    conn.prepare_cached(
+        r#"
+        UPDATE download SET state = ?2, error = NULL WHERE url = ?1 AND state IN (?3, ?4)
+        "#,
+    )?
+    .execute(params!(
+        url,
+        DownloadState::Queued.as_str(),
+        DownloadState::Failed.as_str(),
+        DownloadState::Paused.as_str()
+    ))?;
+    Ok(())
+}
+
+pub fn set_download_state(
+    conn: &Connection,
+    url: &str,
+    state: DownloadState,
+    path: Option<&str>,
+    error: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        UPDATE download SET state = ?2, path = ?3, error = ?4 WHERE url = ?1
+        "#,
+    )?
+    .execute(params!(url, state.as_str(), path, error))?;
+    Ok(())
+}
+
+pub fn remove_download(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        DELETE FROM download WHERE url = ?1
+        "#,
+    )?
+    .execute(params!(url))?;
+    Ok(())
+}
+
+/// Filter history ---------------------------------------------------------------
+
+/// The most recently used filters of the tui, oldest first.
+pub fn filter_history(conn: &Connection, limit: usize) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT filter FROM filter_history ORDER BY used DESC LIMIT ?1
+        "#,
+    )?;
+    let mut res = stmt
+        .query_map(params!(limit as i64), |row| row.get(0))?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+    res.reverse();
+    Ok(res)
+}
+
+pub fn add_to_filter_history(conn: &Connection, filter: &str) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR REPLACE INTO filter_history (filter, used) VALUES (?1, ?2)
+        "#,
+    )?
+    .execute(params!(filter, to_string(&chrono::Local::now().into())))?;
+    Ok(())
+}
+
+/// Deleted items ----------------------------------------------------------------
+
+/// Number of deletions that are kept for `restore_deleted`.
+const DELETED_ITEMS_KEPT: i64 = 100;
+
+fn deleted_from_row(row: &rusqlite::Row) -> Result<Deleted, rusqlite::Error> {
+    let list: String = row.get(1)?;
+    let item = if list == "active" {
+        DeletedItem::Active(Active {
+            url: row.get(3)?,
+            title: row.get(4)?,
+            duration_secs: row.get(5)?,
+            position_secs: row.get(6)?,
+            feed_title: row.get(7)?,
+            priority: row.get(8)?,
+            last_played: get_optional_date(row, 9)?,
+            chapter: row.get(10)?,
+            chapter_count: row.get(11)?,
+            chapter_start_secs: row.get(12)?,
+        })
+    } else {
+        let feedurl: String = row.get(14)?;
+        DeletedItem::Available(Available {
+            url: row.get(3)?,
+            title: row.get(4)?,
+            duration_secs: row.get(5)?,
+            publication: get_date(row, 13)?,
+            feed: Feed {
+                title: row.get::<_, Option<String>>(15)?.unwrap_or(feedurl.clone()),
+                lastupdate: get_optional_date(row, 16)?,
+                url: feedurl,
+            },
+            added: None,
+        })
+    };
+    Ok(Deleted {
+        id: row.get(0)?,
+        deleted: get_date(row, 2)?,
+        item,
+    })
+}
+
+const SELECT_DELETED: &str = r#"
+    SELECT id, list, deleted, url, deleted_item.title, duration_secs, position_secs, feed_title,
+        priority, last_played, chapter, chapter_count, chapter_start_secs, publication, feedurl,
+        feed.title, lastupdate
+    FROM deleted_item LEFT JOIN feed USING(feedurl)
+"#;
+
+/// Remember a removed video. Only the most recent deletions are kept.
+pub fn record_deletion(conn: &Connection, item: &DeletedItem) -> Result<(), rusqlite::Error> {
+    let deleted = to_string(&chrono::Local::now().into());
+    match item {
+        DeletedItem::Active(a) => conn
+            .prepare_cached(
+                r#"
+                INSERT INTO deleted_item (
+                    list, deleted, url, title, duration_secs, position_secs, feed_title,
+                    priority, last_played, chapter, chapter_count, chapter_start_secs
+                )
+                VALUES ('active', ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                "#,
+            )?
+            .execute(params!(
+                deleted,
+                a.url,
+                a.title,
+                a.duration_secs,
+                a.position_secs,
+                a.feed_title,
+                a.priority,
+                a.last_played.as_ref().map(to_string),
+                a.chapter,
+                a.chapter_count,
+                a.chapter_start_secs
+            ))?,
+        DeletedItem::Available(a) => conn
+            .prepare_cached(
+                r#"
+                INSERT INTO deleted_item (
+                    list, deleted, url, title, duration_secs, publication, feedurl
+                )
+                VALUES ('available', ?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )?
+            .execute(params!(
+                deleted,
+                a.url,
+                a.title,
+                a.duration_secs,
+                to_string(&a.publication),
+                a.feed.url
+            ))?,
+    };
+    conn.prepare_cached(
+        r#"
+        DELETE FROM deleted_item
+        WHERE id NOT IN (SELECT id FROM deleted_item ORDER BY id DESC LIMIT ?1)
+        "#,
+    )?
+    .execute(params!(DELETED_ITEMS_KEPT))?;
+    Ok(())
+}
+
+/// Remove the video from the active and available videos and remember it for
+/// `restore_deleted`.
+pub fn delete_video(conn: &Connection, url: &str) -> Result<(), rusqlite::Error> {
+    in_transaction(conn, || {
+        if let Some(active) = find_in_active(conn, url)? {
+            record_deletion(conn, &DeletedItem::Active(active))?;
+            remove_from_active(conn, url)?;
+        }
+        if let Some(available) = find_in_available(conn, url)? {
+            record_deletion(conn, &DeletedItem::Available(available))?;
+            remove_from_available(conn, url)?;
+        }
+        Ok(())
+    })
+}
+
+/// The most recent deletions, newest first.
+pub fn iter_deleted(conn: &Connection, limit: usize) -> Result<Vec<Deleted>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(&format!("{} ORDER BY id DESC LIMIT ?1", SELECT_DELETED))?;
+    let res = stmt
+        .query_map(params!(limit as i64), deleted_from_row)?
+        .collect::<Result<Vec<_>, rusqlite::Error>>();
+    res
+}
+
+pub fn find_deleted(conn: &Connection, id: i64) -> Result<Option<Deleted>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(&format!("{} WHERE id = ?1", SELECT_DELETED))?;
+    let res = stmt.query_map(params!(id), deleted_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// The most recent deletion from the active (or else available) list.
+pub fn last_deleted(conn: &Connection, active: bool) -> Result<Option<Deleted>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "{} WHERE list = ?1 ORDER BY id DESC LIMIT 1",
+        SELECT_DELETED
+    ))?;
+    let list = if active { "active" } else { "available" };
+    let res = stmt.query_map(params!(list), deleted_from_row)?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// Add a deleted item to its list again. Items that are in the list already are left unchanged.
+/// Restore a removed video to the list it has been removed from. Available videos of feeds that
+/// have been removed since are made active instead. Returns the name of the list (see
+/// `DeletedItem::list`).
+pub fn restore_deleted(
+    conn: &Connection,
+    deleted: &Deleted,
+) -> Result<&'static str, rusqlite::Error> {
+    in_transaction(conn, || {
+        let (res, list) = match &deleted.item {
+            DeletedItem::Available(a) if find_feed(conn, &a.feed.url)?.is_none() => {
+                let active = Active {
+                    url: a.url.clone(),
+                    title: Some(a.title.clone()),
+                    position_secs: 0.0,
+                    duration_secs: a.duration_secs,
+                    feed_title: Some(a.feed.title.clone()),
+                    priority: 0,
+                    last_played: None,
+                    chapter: None,
+                    chapter_count: None,
+                    chapter_start_secs: None,
+                };
+                (add_to_active(conn, &active), "active")
+            }
+            item @ DeletedItem::Active(a) => (add_to_active(conn, a), item.list()),
+            item @ DeletedItem::Available(a) => (add_to_available(conn, a), item.list()),
+        };
+        // The video may have been added again in the meantime.
+        crate::ignore_constraint_errors(res)?;
+        conn.prepare_cached(
+            r#"
+            DELETE FROM deleted_item WHERE id = ?1
+            "#,
+        )?
+        .execute(params!(deleted.id))?;
+        Ok(list)
+    })
+}
+
+/// State ------------------------------------------------------------------------
+
+const STATE_LAST_TUI_SESSION: &str = "last_tui_session";
+
+/// When the tui has been started last, if ever.
+pub fn last_tui_session(conn: &Connection) -> Result<Option<DateTime>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT value FROM state WHERE key = ?1
+        "#,
+    )?;
+    let res = stmt.query_map(params!(STATE_LAST_TUI_SESSION), |row| get_date(row, 0))?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+pub fn set_last_tui_session(conn: &Connection, start: &DateTime) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR REPLACE INTO state (key, value) VALUES (?1, ?2)
+        "#,
+    )?
+    .execute(params!(STATE_LAST_TUI_SESSION, to_string(start)))?;
+    Ok(())
+}
+
+/// The lease on `url` if it has not expired yet.
+pub fn playback_lease(
+    conn: &Connection,
+    url: &str,
+) -> Result<Option<PlaybackLease>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT url, holder, expires FROM playback_lease
+        WHERE url = ?1 AND julianday(expires) > julianday('now')
+        "#,
+    )?;
+    let res = stmt.query_map(params!(url), |row| {
+        Ok(PlaybackLease {
+            url: row.get(0)?,
+            holder: row.get(1)?,
+            expires: get_date(row, 2)?,
+        })
+    })?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// The url of the video that is being played (by any instance of uvp), if any: the one with the
+/// most recently renewed lease. Videos of instances that have crashed drop out once their lease
+/// expires.
+pub fn now_playing(conn: &Connection) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT url FROM playback_lease
+        WHERE julianday(expires) > julianday('now')
+        ORDER BY julianday(expires) DESC
+        LIMIT 1
+        "#,
     )?;
+    let res = stmt.query_map(params![], |row| row.get(0))?;
+    let mut iter = res.into_iter();
+    Ok(iter.next().transpose()?)
+}
+
+/// Take over or renew the lease on `url`.
+pub fn set_playback_lease(
+    conn: &Connection,
+    url: &str,
+    holder: &str,
+    expires: &DateTime,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        INSERT OR REPLACE INTO playback_lease (url, holder, expires) VALUES (?1, ?2, ?3)
+        "#,
+    )?
+    .execute(params!(url, holder, to_string(expires)))?;
     Ok(())
 }
+
+/// Release the lease on `url` unless it has been taken over by another holder.
+pub fn release_playback_lease(
+    conn: &Connection,
+    url: &str,
+    holder: &str,
+) -> Result<(), rusqlite::Error> {
+    conn.prepare_cached(
+        r#"
+        DELETE FROM playback_lease WHERE url = ?1 AND holder = ?2
+        "#,
+    )?
+    .execute(params!(url, holder))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tables of the first version of uvp, before any migration.
+    const BASELINE_TABLE_DEFINITIONS: &str = r#"
+    CREATE TABLE feed (
+        feedurl         TEXT PRIMARY KEY,
+        title           TEXT NOT NULL,
+        lastupdate      Text
+    );
+    CREATE TABLE available (
+        title          TEXT NOT NULL,
+        url            TEXT PRIMARY KEY,
+        publication    TEXT NOT NULL,
+        feedurl        TEXT NOT NULL,
+        FOREIGN KEY(feedurl) REFERENCES feed
+    );
+    CREATE TABLE active (
+        url            TEXT PRIMARY KEY,
+        title          TEXT,
+        position_secs  FLOAT NOT NULL,
+        duration_secs  FLOAT,
+        feed_title     TEXT
+    );
+    "#;
+
+    /// Table, name, type, not null, default and primary key of all columns.
+    fn schema(conn: &Connection) -> Vec<(String, String, String, bool, Option<String>, i64)> {
+        let mut stmt = conn
+            .prepare(
+                r#"
+                SELECT m.name, p.name, p.type, p."notnull", p.dflt_value, p.pk
+                FROM sqlite_master AS m JOIN pragma_table_info(m.name) AS p
+                WHERE m.type = 'table'
+                ORDER BY m.name, p.cid
+                "#,
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map(params![], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })
+            .unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn new_database_matches_migrated_database() {
+        let new = Connection::open_in_memory().unwrap();
+        init_db(&new).unwrap();
+
+        let migrated = Connection::open_in_memory().unwrap();
+        migrated.execute_batch(BASELINE_TABLE_DEFINITIONS).unwrap();
+        init_db(&migrated).unwrap();
+
+        assert_eq!(schema(&new), schema(&migrated));
+        let version = |conn: &Connection| -> i64 {
+            conn.query_row("PRAGMA user_version", params![], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(version(&new), MIGRATIONS.len() as i64);
+        assert_eq!(version(&migrated), MIGRATIONS.len() as i64);
+    }
+}
@@ -0,0 +1,358 @@
+//! Downloads of videos for offline playback using yt-dlp (or a compatible program).
+//!
+//! Downloads are queued in the database (`uvp download <url>` or automatically during a refresh
+//! for feeds with `auto_download`) and processed by a `Downloader` with at most
+//! `download_concurrency` processes at a time.
+use crate::data::{
+    find_download, find_in_active, find_in_available, iter_downloads, remove_download,
+    set_download_state, Download, DownloadState,
+};
+use crate::{Error, Settings};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::JoinHandle;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Marks the progress lines in the output of yt-dlp (see `--progress-template` below).
+const PROGRESS_PREFIX: &str = "[uvp-progress]";
+
+/// A running download and the threads reading its output. The threads report progress and
+/// return the last other line they have read.
+struct Process {
+    url: String,
+    child: Child,
+    stdout: JoinHandle<Option<String>>,
+    stderr: JoinHandle<Option<String>>,
+}
+
+fn read_output(
+    url: String,
+    pipe: impl Read + Send + 'static,
+    progress: Sender<(String, f64)>,
+) -> JoinHandle<Option<String>> {
+    std::thread::spawn(move || {
+        let mut last = None;
+        for line in BufReader::new(pipe).lines() {
+            let line = if let Ok(line) = line { line } else { break };
+            if let Some(percent) = line.trim().strip_prefix(PROGRESS_PREFIX) {
+                if let Ok(percent) = percent.trim().trim_end_matches('%').parse() {
+                    let _ = progress.send((url.clone(), percent));
+                }
+            } else if !line.trim().is_empty() {
+                last = Some(line.trim().to_owned());
+            }
+        }
+        last
+    })
+}
+
+fn start(
+    conn: &Connection,
+    settings: &Settings,
+    url: &str,
+    progress: &Sender<(String, f64)>,
+) -> Result<Process, Error> {
+    let feed_title = match find_in_available(conn, url)? {
+        Some(available) => Some(available.feed.title),
+        None => find_in_active(conn, url)?.and_then(|a| a.feed_title),
+    };
+    let mut command = Command::new(&settings.download_binary);
+    if let Some(quality) = settings.quality(feed_title.as_deref()) {
+        command
+            .arg("--format")
+            .arg(crate::mpv::ytdl_format(quality));
+    }
+    if let Some(proxy) = settings.proxy(feed_title.as_deref()) {
+        command.arg("--proxy").arg(proxy);
+    }
+    let mut child = command
+        .arg("--paths")
+        .arg(&settings.download_dir)
+        .arg("--output")
+        .arg("%(title)s [%(id)s].%(ext)s")
+        .arg("--print")
+        .arg("after_move:filepath")
+        // The modification time is the time of the download, which `prune` relies on.
+        .arg("--no-mtime")
+        .arg("--progress")
+        .arg("--newline")
+        .arg("--progress-template")
+        .arg(format!(
+            "download:{} %(progress._percent_str)s",
+            PROGRESS_PREFIX
+        ))
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_output(
+        url.to_owned(),
+        child.stdout.take().unwrap(),
+        progress.clone(),
+    );
+    let stderr = read_output(
+        url.to_owned(),
+        child.stderr.take().unwrap(),
+        progress.clone(),
+    );
+    Ok(Process {
+        url: url.to_owned(),
+        child,
+        stdout,
+        stderr,
+    })
+}
+
+/// Runs queued downloads in the background. `poll` has to be called regularly to start queued
+/// downloads and to record finished ones.
+pub struct Downloader {
+    processes: Vec<Process>,
+    progress: HashMap<String, f64>,
+    progress_sender: Sender<(String, f64)>,
+    progress_receiver: Receiver<(String, f64)>,
+}
+
+impl Downloader {
+    pub fn new() -> Self {
+        let (progress_sender, progress_receiver) = std::sync::mpsc::channel();
+        Downloader {
+            processes: Vec::new(),
+            progress: HashMap::new(),
+            progress_sender,
+            progress_receiver,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.processes.is_empty()
+    }
+
+    pub fn is_running(&self, url: &str) -> bool {
+        self.processes.iter().any(|p| p.url == url)
+    }
+
+    /// Progress of a running download in percent, if known.
+    pub fn progress(&self, url: &str) -> Option<f64> {
+        self.progress.get(url).cloned()
+    }
+
+    /// Update the progress, record finished downloads and start queued ones without blocking.
+    /// Returns the downloads that have finished (or failed) since the last call.
+    pub fn poll(&mut self, conn: &Connection, settings: &Settings) -> Result<Vec<Download>, Error> {
+        for (url, percent) in self.progress_receiver.try_iter() {
+            if self.processes.iter().any(|p| p.url == url) {
+                self.progress.insert(url, percent);
+            }
+        }
+
+        let mut finished = Vec::new();
+        let mut i = 0;
+        while i < self.processes.len() {
+            if let Some(status) = self.processes[i].child.try_wait()? {
+                let process = self.processes.swap_remove(i);
+                self.progress.remove(&process.url);
+                let path = process.stdout.join().unwrap_or(None);
+                let error = process.stderr.join().unwrap_or(None);
+                match (status.success(), path) {
+                    (true, Some(path)) => {
+                        set_download_state(
+                            conn,
+                            &process.url,
+                            DownloadState::Done,
+                            Some(&path),
+                            None,
+                        )?;
+                    }
+                    _ => {
+                        let error = error.unwrap_or_else(|| format!("exited with {}", status));
+                        set_download_state(
+                            conn,
+                            &process.url,
+                            DownloadState::Failed,
+                            None,
+                            Some(&error),
+                        )?;
+                    }
+                }
+                finished.extend(find_download(conn, &process.url)?);
+            } else {
+                i += 1;
+            }
+        }
+        if finished.iter().any(|d| d.state == DownloadState::Done) {
+            prune(conn, settings)?;
+        }
+
+        let concurrency = settings.download_concurrency.max(1);
+        if self.processes.len() < concurrency {
+            for download in iter_downloads(conn)? {
+                if self.processes.len() >= concurrency {
+                    break;
+                }
+                // Downloads that are marked as running without a process have been interrupted.
+                let waiting = download.state == DownloadState::Queued
+                    || (download.state == DownloadState::Running
+                        && !self.is_running(&download.url));
+                if !waiting {
+                    continue;
+                }
+                match start(conn, settings, &download.url, &self.progress_sender) {
+                    Ok(process) => {
+                        set_download_state(
+                            conn,
+                            &download.url,
+                            DownloadState::Running,
+                            None,
+                            None,
+                        )?;
+                        self.processes.push(process);
+                    }
+                    Err(Error::Io(e)) => {
+                        let error = format!("Failed to start {}: {}", settings.download_binary, e);
+                        set_download_state(
+                            conn,
+                            &download.url,
+                            DownloadState::Failed,
+                            None,
+                            Some(&error),
+                        )?;
+                        finished.push(Download {
+                            state: DownloadState::Failed,
+                            error: Some(error),
+                            ..download
+                        });
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(finished)
+    }
+
+    /// Kill the download process of the url, if it is running. Partial downloads are kept, so
+    /// yt-dlp continues where it stopped when the download is started again.
+    fn kill(&mut self, url: &str) -> Result<(), Error> {
+        if let Some(i) = self.processes.iter().position(|p| p.url == url) {
+            let mut process = self.processes.swap_remove(i);
+            let _ = process.child.kill();
+            process.child.wait()?;
+            self.progress.remove(url);
+        }
+        Ok(())
+    }
+
+    pub fn pause(&mut self, conn: &Connection, url: &str) -> Result<(), Error> {
+        self.kill(url)?;
+        set_download_state(conn, url, DownloadState::Paused, None, None)?;
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, conn: &Connection, url: &str) -> Result<(), Error> {
+        self.kill(url)?;
+        remove_download(conn, url)?;
+        Ok(())
+    }
+
+    /// Stop all running downloads. They are queued again and continued by the next `poll`, e.g.,
+    /// in the next session.
+    pub fn stop(&mut self, conn: &Connection) -> Result<(), Error> {
+        let urls = self
+            .processes
+            .iter()
+            .map(|p| p.url.clone())
+            .collect::<Vec<_>>();
+        for url in urls {
+            self.kill(&url)?;
+            set_download_state(conn, &url, DownloadState::Queued, None, None)?;
+        }
+        Ok(())
+    }
+}
+
+/// Delete downloaded files that are older than `download_keep_days` and, while the files take up
+/// more than `download_max_bytes`, the oldest files of watched videos (i.e., neither active nor
+/// available) and then the oldest of the others. Returns the deleted downloads.
+pub fn prune(conn: &Connection, settings: &Settings) -> Result<Vec<Download>, Error> {
+    let mut files = Vec::new();
+    for download in iter_downloads(conn)? {
+        let path = match (&download.state, &download.path) {
+            (DownloadState::Done, Some(path)) => path,
+            _ => continue,
+        };
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // The file has been deleted by the user.
+                remove_download(conn, &download.url)?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let watched = find_in_active(conn, &download.url)?.is_none()
+            && find_in_available(conn, &download.url)?.is_none();
+        files.push((watched, metadata.modified()?, metadata.len(), download));
+    }
+    files.sort_by_key(|(watched, modified, _, _)| (!*watched, *modified));
+
+    let now = std::time::SystemTime::now();
+    let mut total_bytes = files.iter().map(|(_, _, len, _)| len).sum::<u64>();
+    let mut pruned = Vec::new();
+    for (_, modified, len, download) in files {
+        let expired = settings.download_keep_days.map_or(false, |days| {
+            now.duration_since(modified)
+                .map_or(false, |age| age.as_secs() > days * 24 * 60 * 60)
+        });
+        let over_quota = settings
+            .download_max_bytes
+            .map_or(false, |max_bytes| total_bytes > max_bytes);
+        if expired || over_quota {
+            match std::fs::remove_file(download.path.as_deref().unwrap()) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            remove_download(conn, &download.url)?;
+            total_bytes -= len;
+            pruned.push(download);
+        }
+    }
+    Ok(pruned)
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Download all queued videos and block until all downloads have finished or failed.
+///
+/// `on_done` is called with the path of each finished download; failures are printed to stderr.
+pub fn run_queue(
+    conn: &Connection,
+    settings: &Settings,
+    mut on_done: impl FnMut(&str),
+) -> Result<(), Error> {
+    let mut downloader = Downloader::new();
+    loop {
+        for download in downloader.poll(conn, settings)? {
+            match download.state {
+                DownloadState::Done => on_done(download.path.as_deref().unwrap_or(&download.url)),
+                _ => eprintln!(
+                    "Failed to download {}: {}",
+                    download.url,
+                    download.error.as_deref().unwrap_or("unknown error")
+                ),
+            }
+        }
+        if downloader.is_idle() {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
@@ -1,14 +1,18 @@
 use atom_syndication;
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, TimeZone};
 use rss;
+use serde_json::json;
 
 use std::str::FromStr;
 
+use crate::data::{AuthKind, FeedAuth};
 use crate::Error;
 
 pub enum FeedEntries {
     Atom(Box<atom_syndication::Feed>),
     RSS(Box<rss::Channel>),
+    Mediathek(Vec<Entry>),
+    Directory(Vec<Entry>),
 }
 
 fn parse_time(s: &str) -> chrono::ParseResult<DateTime<FixedOffset>> {
@@ -17,11 +21,98 @@ fn parse_time(s: &str) -> chrono::ParseResult<DateTime<FixedOffset>> {
     }
     DateTime::parse_from_rfc3339(s)
 }
+/// The absolute path of a local file or directory (also given as a `file://` url), if `url` is
+/// one that exists.
+pub fn local_path(url: &str) -> Option<std::path::PathBuf> {
+    let path = match url.strip_prefix("file://") {
+        Some(path) => path,
+        None if url.contains("://") => return None,
+        None => url,
+    };
+    std::fs::canonicalize(path).ok()
+}
+
+/// The canonical form `https://www.youtube.com/watch?v=<id>` of links to youtube videos, e.g.,
+/// `youtu.be/<id>`, `youtube.com/shorts/<id>` or `watch?v=<id>&t=42`. Local files are stored with
+/// their absolute path (see `local_path`). Other urls are returned as they are, so that a video
+/// has only one url in the database.
+pub fn normalize_url(url: &str) -> String {
+    if let Some(path) = local_path(url) {
+        return path.to_string_lossy().into_owned();
+    }
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let rest = ["www.", "m.", "music."]
+        .iter()
+        .find_map(|prefix| rest.strip_prefix(prefix))
+        .unwrap_or(rest);
+    let id = if let Some(path) = rest.strip_prefix("youtu.be/") {
+        Some(path)
+    } else if let Some(path) = rest.strip_prefix("youtube.com/") {
+        if let Some(query) = path.strip_prefix("watch?") {
+            query
+                .split(|c| c == '&' || c == '#')
+                .find_map(|param| param.strip_prefix("v="))
+        } else {
+            ["shorts/", "embed/", "live/", "v/"]
+                .iter()
+                .find_map(|prefix| path.strip_prefix(prefix))
+        }
+    } else {
+        None
+    };
+    match id.map(|id| {
+        id.split(|c| c == '?' || c == '&' || c == '#' || c == '/')
+            .next()
+            .unwrap()
+    }) {
+        Some(id) if !id.is_empty() => youtube_video_url(id),
+        _ => url.to_owned(),
+    }
+}
+
+const YOUTUBE_WATCH_URL: &str = "https://www.youtube.com/watch?v=";
+const YOUTUBE_CHANNEL_FEED_URL: &str = "https://www.youtube.com/feeds/videos.xml?channel_id=";
+
+/// The canonical url of the youtube video with the id (see `normalize_url`).
+pub fn youtube_video_url(id: &str) -> String {
+    format!("{}{}", YOUTUBE_WATCH_URL, id)
+}
+
+/// The id of a youtube video url in the canonical form of `normalize_url`.
+pub fn youtube_video_id(url: &str) -> Option<&str> {
+    url.strip_prefix(YOUTUBE_WATCH_URL)
+}
+
+/// The channel id of a youtube feed url (see `youtube_url_channelid`).
+pub fn youtube_channel_id(url: &str) -> Option<&str> {
+    url.strip_prefix(YOUTUBE_CHANNEL_FEED_URL)
+}
+
 #[derive(Debug, Clone)]
 pub struct Entry {
     pub title: String,
     pub url: String,
     pub publication: crate::data::DateTime,
+    pub duration_secs: Option<f64>,
+    /// The feed links to the entry as a youtube short (`/shorts/<id>`).
+    pub short_link: bool,
+    /// The rss guid or atom id of the entry.
+    pub guid: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Entry {
+    /// Identifies the entry within its feed: the guid or, if there is none, the url.
+    pub fn id(&self) -> &str {
+        self.guid.as_deref().unwrap_or(&self.url)
+    }
+}
+
+fn is_short_link(url: &str) -> bool {
+    url.contains("youtube.com/shorts/")
 }
 
 impl FeedEntries {
@@ -29,15 +120,53 @@ impl FeedEntries {
         match self {
             FeedEntries::Atom(f) => f.entries().iter().filter_map(entry_from_atom).collect(),
             FeedEntries::RSS(c) => c.items().iter().filter_map(entry_from_rss).collect(),
+            FeedEntries::Mediathek(entries) | FeedEntries::Directory(entries) => entries.clone(),
         }
     }
 }
 
+/// Parse durations of the form "3723", "62:03" or "1:02:03".
+fn parse_duration(s: &str) -> Option<f64> {
+    s.trim().split(':').try_fold(0.0, |secs, part| {
+        Some(secs * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+fn atom_duration(entry: &atom_syndication::Entry) -> Option<f64> {
+    let media = entry.extensions().get("media")?;
+    let content = media
+        .get("content")
+        .or_else(|| media.get("group")?.first()?.children().get("content"))?;
+    content
+        .iter()
+        .find_map(|c| parse_duration(c.attrs().get("duration")?))
+}
+
+fn rss_duration(entry: &rss::Item) -> Option<f64> {
+    if let Some(duration) = entry.itunes_ext().and_then(|itunes| itunes.duration()) {
+        return parse_duration(duration);
+    }
+    let media = entry.extensions().get("media")?;
+    let content = media
+        .get("content")
+        .or_else(|| media.get("group")?.first()?.children().get("content"))?;
+    content
+        .iter()
+        .find_map(|c| parse_duration(c.attrs().get("duration")?))
+}
+
 fn entry_from_atom(entry: &atom_syndication::Entry) -> Option<Entry> {
+    let url = entry.links().first()?.href();
     Some(Entry {
         title: entry.title().to_owned(),
-        url: entry.links().first()?.href().to_owned(),
-        publication: parse_time(entry.published()?).unwrap(),
+        url: normalize_url(url),
+        publication: parse_time(entry.published()?).ok()?,
+        duration_secs: atom_duration(entry),
+        short_link: is_short_link(url),
+        guid: Some(entry.id())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_owned()),
+        description: entry.summary().map(|s| s.to_owned()),
     })
 }
 fn entry_from_rss(entry: &rss::Item) -> Option<Entry> {
@@ -48,8 +177,12 @@ fn entry_from_rss(entry: &rss::Item) -> Option<Entry> {
 
     Some(Entry {
         title: entry.title()?.to_owned(),
-        url,
-        publication: parse_time(entry.pub_date()?).unwrap(),
+        url: normalize_url(&url),
+        publication: parse_time(entry.pub_date()?).ok()?,
+        duration_secs: rss_duration(entry),
+        short_link: is_short_link(&url),
+        guid: entry.guid().map(|guid| guid.value().to_owned()),
+        description: entry.description().map(|d| d.to_owned()),
     })
 }
 
@@ -62,8 +195,262 @@ fn parse(xml: &str) -> Result<FeedEntries, Error> {
     )))
 }
 
-pub async fn fetch(client: &reqwest::Client, url: &str) -> Result<FeedEntries, Error> {
-    let xml_resp = client.get(url).send().await?.text().await?;
-    println!("Fetched from url: {}", url);
-    Ok(parse(&xml_resp)?)
+pub const MEDIATHEK_API_URL: &str = "https://mediathekviewweb.de/api/query";
+
+/// Number of broadcasts that are requested from the MediathekViewWeb api.
+const MEDIATHEK_RESULT_SIZE: u64 = 50;
+
+/// A query of the MediathekViewWeb json api. It is stored as the url of its feed, i.e., the url
+/// of the api with the parameters of the query.
+#[derive(Debug, Clone, Default)]
+pub struct MediathekQuery {
+    /// Searched in titles and topics.
+    pub query: String,
+    pub channel: Option<String>,
+    pub topic: Option<String>,
+    pub min_duration_secs: Option<u64>,
+    /// Include broadcasts that are announced, but not yet available.
+    pub future: bool,
+}
+
+impl MediathekQuery {
+    pub fn to_url(&self) -> String {
+        let mut params = vec![("query", self.query.clone())];
+        if let Some(channel) = &self.channel {
+            params.push(("channel", channel.clone()));
+        }
+        if let Some(topic) = &self.topic {
+            params.push(("topic", topic.clone()));
+        }
+        if let Some(min_duration_secs) = self.min_duration_secs {
+            params.push(("min_duration", min_duration_secs.to_string()));
+        }
+        params.push(("future", self.future.to_string()));
+        reqwest::Url::parse_with_params(MEDIATHEK_API_URL, &params)
+            .expect("valid base url")
+            .to_string()
+    }
+
+    /// The query of a feed url, if it is a url of the MediathekViewWeb api.
+    pub fn from_url(url: &str) -> Option<Self> {
+        if !url.starts_with(MEDIATHEK_API_URL) {
+            return None;
+        }
+        let url = reqwest::Url::parse(url).ok()?;
+        let mut query = MediathekQuery::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "query" => query.query = value.into_owned(),
+                "channel" => query.channel = Some(value.into_owned()),
+                "topic" => query.topic = Some(value.into_owned()),
+                "min_duration" => query.min_duration_secs = value.parse().ok(),
+                "future" => query.future = value == "true",
+                _ => {}
+            }
+        }
+        Some(query)
+    }
+
+    fn request(&self) -> serde_json::Value {
+        let mut queries = Vec::new();
+        if !self.query.is_empty() {
+            queries.push(json!({ "fields": ["title", "topic"], "query": self.query }));
+        }
+        if let Some(channel) = &self.channel {
+            queries.push(json!({ "fields": ["channel"], "query": channel }));
+        }
+        if let Some(topic) = &self.topic {
+            queries.push(json!({ "fields": ["topic"], "query": topic }));
+        }
+        let mut request = json!({
+            "queries": queries,
+            "sortBy": "timestamp",
+            "sortOrder": "desc",
+            "future": self.future,
+            "offset": 0,
+            "size": MEDIATHEK_RESULT_SIZE,
+        });
+        if let Some(min_duration_secs) = self.min_duration_secs {
+            request["duration_min"] = min_duration_secs.into();
+        }
+        request
+    }
+}
+
+fn entry_from_mediathek(result: &serde_json::Value) -> Option<Entry> {
+    let url = ["url_video_hd", "url_video", "url_video_low"]
+        .iter()
+        .find_map(|key| result[*key].as_str().filter(|u| !u.is_empty()))?;
+    let title = match (result["topic"].as_str(), result["title"].as_str()?) {
+        (Some(topic), title) if !topic.is_empty() => format!("{} - {}", topic, title),
+        (_, title) => title.to_owned(),
+    };
+    let publication = chrono::Utc
+        .timestamp_opt(result["timestamp"].as_i64()?, 0)
+        .single()?;
+    Some(Entry {
+        title,
+        url: url.to_owned(),
+        publication: publication.into(),
+        duration_secs: result["duration"].as_f64(),
+        short_link: false,
+        guid: result["id"].as_str().map(|id| id.to_owned()),
+        description: result["description"].as_str().map(|d| d.to_owned()),
+    })
+}
+
+async fn fetch_mediathek(
+    client: &reqwest::Client,
+    query: &MediathekQuery,
+) -> Result<FeedEntries, Error> {
+    let response = client
+        .post(MEDIATHEK_API_URL)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain")
+        .body(query.request().to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response = serde_json::from_str::<serde_json::Value>(&response)
+        .map_err(|e| Error::Mediathek(e.to_string()))?;
+    if let Some(err) = response["err"].as_array().filter(|e| !e.is_empty()) {
+        return Err(Error::Mediathek(
+            err.iter()
+                .map(|e| e.as_str().map_or(e.to_string(), |e| e.to_owned()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+    }
+    let results = response["result"]["results"]
+        .as_array()
+        .ok_or_else(|| Error::Mediathek("missing results".to_owned()))?;
+    Ok(FeedEntries::Mediathek(
+        results.iter().filter_map(entry_from_mediathek).collect(),
+    ))
+}
+
+/// Extensions of the files that are added by directory feeds.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "m4v", "webm", "avi", "mov", "ts", "flv", "wmv", "mpg", "mpeg", "mp3", "m4a",
+    "ogg", "opus", "flac", "wav",
+];
+
+/// The media files in the directory and its subdirectories, published at their modification time.
+fn scan_directory(url: &str) -> Result<FeedEntries, Error> {
+    let dir = local_path(url).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "Directory does not exist")
+    })?;
+    let mut entries = Vec::new();
+    let mut dirs = vec![dir];
+    while let Some(dir) = dirs.pop() {
+        for file in std::fs::read_dir(&dir)? {
+            let file = file?;
+            let path = file.path();
+            let metadata = file.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let is_media = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map_or(false, |e| {
+                    MEDIA_EXTENSIONS.contains(&e.to_lowercase().as_str())
+                });
+            if !is_media {
+                continue;
+            }
+            let modified: chrono::DateTime<chrono::Local> = metadata.modified()?.into();
+            entries.push(Entry {
+                title: path
+                    .file_stem()
+                    .map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+                url: path.to_string_lossy().into_owned(),
+                publication: modified.into(),
+                duration_secs: None,
+                short_link: false,
+                guid: None,
+                description: None,
+            });
+        }
+    }
+    Ok(FeedEntries::Directory(entries))
+}
+
+/// Whether the feed url is a local directory (see `scan_directory`).
+fn is_directory_feed(url: &str) -> bool {
+    url.starts_with("file://") || !url.contains("://")
+}
+
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    auth: Option<&FeedAuth>,
+) -> Result<FeedEntries, Error> {
+    let fetch = async {
+        if is_directory_feed(url) {
+            // Walking a large directory would hold up the fetches of all other feeds.
+            let url = url.to_owned();
+            return tokio::task::spawn_blocking(move || scan_directory(&url))
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        if let Some(query) = MediathekQuery::from_url(url) {
+            return fetch_mediathek(client, &query).await;
+        }
+        let mut request = client.get(url);
+        if let Some(auth) = auth {
+            let secret = auth.secret.get()?;
+            request = match &auth.kind {
+                AuthKind::Basic { user } => request.basic_auth(user, Some(secret)),
+                AuthKind::Header { name } => request.header(name.as_str(), secret),
+            };
+        }
+        let xml_resp = request.send().await?.error_for_status()?.text().await?;
+        parse(&xml_resp)
+    };
+    fetch.await.map_err(|e| e.in_feed(url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NORMALIZED: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+
+    #[test]
+    fn normalize_youtube_urls() {
+        for url in &[
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ?t=42",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://youtube.com/shorts/dQw4w9WgXcQ?feature=share",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube.com/live/dQw4w9WgXcQ?si=abc",
+            "https://www.youtube.com/v/dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s",
+            "https://www.youtube.com/watch?feature=share&v=dQw4w9WgXcQ&list=PL123",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=1m",
+            "http://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&si=abc",
+            "youtube.com/watch?v=dQw4w9WgXcQ",
+            NORMALIZED,
+        ] {
+            assert_eq!(normalize_url(url), NORMALIZED, "{}", url);
+        }
+    }
+
+    #[test]
+    fn keep_other_urls() {
+        for url in &[
+            "https://www.youtube.com/watch?list=PL123",
+            "https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw",
+            "https://youtu.be/",
+            "https://example.com/watch?v=dQw4w9WgXcQ",
+            "https://media.example.com/episode-1.mp4",
+        ] {
+            assert_eq!(normalize_url(url), *url);
+        }
+    }
 }
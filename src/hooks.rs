@@ -0,0 +1,276 @@
+//! External hook scripts that are run at certain points of uvp's operation.
+//!
+//! Each hook is a shell command configured in the `[hooks]` section of the config file. It
+//! receives a JSON object on stdin that always contains the name of the hook (`"hook"`) and
+//! further information depending on the hook point. The command may print a JSON object to
+//! stdout to influence uvp:
+//!
+//! * `{"veto": true}` skips the action (pre_refresh, pre_play, on_add).
+//! * `{"title": "..."}` replaces the title of the entry (on_add).
+//!
+//! Empty output leaves everything unchanged. Failing hooks do not stop uvp, their errors and
+//! anything they write to stderr are printed to stderr or, while the tui is running, shown in
+//! its messages.
+//!
+//! Hook points:
+//!
+//! * `pre_refresh`: Before feeds are fetched. Input: `feeds`.
+//! * `post_refresh`: After a feed has been updated. Input: `feed`, `entries` (the new entries).
+//! * `pre_play`: Before a video is played. Input: `url`, `title`.
+//! * `post_play`: After playback. Input: `url`, `title`, `position_secs`, `duration_secs`,
+//!   `finished`.
+//! * `on_add`: Before an entry is added to the list of available videos during a refresh or
+//!   before a video is added using `uvp add video`. Input: `entry`, `feed` (or null).
+//! * `on_new_entry`: After an entry has been added to the available videos during a refresh.
+//!   Input: `feed`, `entry`.
+//! * `on_refresh_complete`: After all feeds have been refreshed. Input: `feeds` (with `title`,
+//!   `url`, `fetched`, `new_entries`, `error` and `not_found` of each feed), `new_entries` (the
+//!   total number).
+//! * `on_playback_finished`: After a video has been played to its end (see
+//!   `completion_threshold`) and removed from the active videos. Input: `url`, `title`,
+//!   `duration_secs`.
+use crate::data::Feed;
+use crate::feeds::Entry;
+use crate::RefreshReport;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+pub const HOOKS_CONFIG_KEY: &str = "hooks";
+
+#[derive(Debug, Default)]
+pub struct Response {
+    pub veto: bool,
+    pub title: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    pre_refresh: Option<String>,
+    post_refresh: Option<String>,
+    pre_play: Option<String>,
+    post_play: Option<String>,
+    on_add: Option<String>,
+    on_new_entry: Option<String>,
+    on_refresh_complete: Option<String>,
+    on_playback_finished: Option<String>,
+    /// Errors of hooks that are kept for `take_errors` instead of being printed (see
+    /// `collect_errors`).
+    errors: Mutex<Option<Vec<String>>>,
+}
+
+pub fn feed_json(feed: &Feed) -> Value {
+    json!({
+        "title": feed.title,
+        "url": feed.url,
+        "lastupdate": feed.lastupdate.map(|l| l.to_rfc3339()),
+    })
+}
+
+pub fn entry_json(entry: &Entry) -> Value {
+    json!({
+        "title": entry.title,
+        "url": entry.url,
+        "publication": entry.publication.to_rfc3339(),
+        "duration_secs": entry.duration_secs,
+        "description": entry.description,
+    })
+}
+
+impl Hooks {
+    pub fn from_config(config: &config::Config) -> Self {
+        let get = |name: &str| {
+            config
+                .get_string(&format!("{}.{}", HOOKS_CONFIG_KEY, name))
+                .ok()
+        };
+        Hooks {
+            pre_refresh: get("pre_refresh"),
+            post_refresh: get("post_refresh"),
+            pre_play: get("pre_play"),
+            post_play: get("post_play"),
+            on_add: get("on_add"),
+            on_new_entry: get("on_new_entry"),
+            on_refresh_complete: get("on_refresh_complete"),
+            on_playback_finished: get("on_playback_finished"),
+            errors: Mutex::new(None),
+        }
+    }
+
+    /// Keep errors of hooks for `take_errors` from now on instead of printing them to stderr,
+    /// e.g. while the tui occupies the terminal.
+    pub fn collect_errors(&self) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.is_none() {
+            *errors = Some(Vec::new());
+        }
+    }
+
+    /// The errors of hooks since the last call (see `collect_errors`).
+    pub fn take_errors(&self) -> Vec<String> {
+        self.errors
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn report_error(&self, message: String) {
+        match self.errors.lock().unwrap().as_mut() {
+            Some(errors) => errors.push(message),
+            None => eprintln!("{}", message),
+        }
+    }
+
+    fn run(&self, hook: &str, command: &Option<String>, mut input: Value) -> Response {
+        let command = if let Some(command) = command {
+            command
+        } else {
+            return Response::default();
+        };
+        input["hook"] = hook.into();
+
+        let output = match run_command(command, &input) {
+            Ok(output) => output,
+            Err(e) => {
+                self.report_error(format!("Failed to run hook {}: {}", hook, e));
+                return Response::default();
+            }
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            self.report_error(format!("Hook {}: {}", hook, stderr.trim()));
+        }
+        if !output.status.success() {
+            self.report_error(format!("Hook {} exited with {}", hook, output.status));
+            return Response::default();
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match stdout.trim() {
+            "" => Response::default(),
+            stdout => match serde_json::from_str::<Value>(stdout) {
+                Ok(response) => Response {
+                    veto: response["veto"].as_bool().unwrap_or(false),
+                    title: response["title"].as_str().map(|t| t.to_owned()),
+                },
+                Err(e) => {
+                    self.report_error(format!("Invalid output of hook {}: {}", hook, e));
+                    Response::default()
+                }
+            },
+        }
+    }
+
+    pub fn pre_refresh(&self, feeds: &[Feed]) -> Response {
+        let feeds = feeds.iter().map(feed_json).collect::<Vec<_>>();
+        self.run("pre_refresh", &self.pre_refresh, json!({ "feeds": feeds }))
+    }
+
+    pub fn post_refresh(&self, feed: &Feed, entries: &[Entry]) {
+        let entries = entries.iter().map(entry_json).collect::<Vec<_>>();
+        self.run(
+            "post_refresh",
+            &self.post_refresh,
+            json!({ "feed": feed_json(feed), "entries": entries }),
+        );
+    }
+
+    pub fn pre_play(&self, url: &str, title: Option<&str>) -> Response {
+        self.run(
+            "pre_play",
+            &self.pre_play,
+            json!({ "url": url, "title": title }),
+        )
+    }
+
+    pub fn post_play(
+        &self,
+        url: &str,
+        title: Option<&str>,
+        position_secs: Option<f64>,
+        duration_secs: Option<f64>,
+        finished: bool,
+    ) {
+        self.run(
+            "post_play",
+            &self.post_play,
+            json!({
+                "url": url,
+                "title": title,
+                "position_secs": position_secs,
+                "duration_secs": duration_secs,
+                "finished": finished,
+            }),
+        );
+    }
+
+    pub fn on_add(&self, feed: Option<&Feed>, entry: Value) -> Response {
+        self.run(
+            "on_add",
+            &self.on_add,
+            json!({ "feed": feed.map(feed_json), "entry": entry }),
+        )
+    }
+
+    pub fn on_new_entry(&self, feed: &Feed, entry: &Entry) {
+        self.run(
+            "on_new_entry",
+            &self.on_new_entry,
+            json!({ "feed": feed_json(feed), "entry": entry_json(entry) }),
+        );
+    }
+
+    pub fn on_refresh_complete(&self, report: &RefreshReport) {
+        let feeds = report
+            .feeds
+            .iter()
+            .map(|f| {
+                json!({
+                    "title": f.title,
+                    "url": f.url,
+                    "fetched": f.fetched,
+                    "new_entries": f.new_entries,
+                    "error": f.error.as_ref().map(|e| e.to_string()),
+                    "not_found": f.error.as_ref().map_or(false, |e| e.is_permanent()),
+                })
+            })
+            .collect::<Vec<_>>();
+        self.run(
+            "on_refresh_complete",
+            &self.on_refresh_complete,
+            json!({ "feeds": feeds, "new_entries": report.new_entries() }),
+        );
+    }
+
+    pub fn on_playback_finished(&self, url: &str, title: Option<&str>, duration_secs: Option<f64>) {
+        self.run(
+            "on_playback_finished",
+            &self.on_playback_finished,
+            json!({ "url": url, "title": title, "duration_secs": duration_secs }),
+        );
+    }
+}
+
+/// Run the command with `input` on stdin and wait for it to exit.
+fn run_command(command: &str, input: &Value) -> std::io::Result<std::process::Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Written from another thread, so that hooks can write their output before having read all
+    // of the input. Hooks that do not read their input at all close the pipe early.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    let writer = std::thread::spawn(move || match stdin.write_all(input.as_bytes()) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    });
+    let output = child.wait_with_output()?;
+    writer.join().unwrap()?;
+    Ok(output)
+}
@@ -0,0 +1,712 @@
+use atom_syndication;
+use reqwest;
+use rss;
+use rusqlite::Connection;
+use structopt::StructOpt;
+
+pub mod backup;
+pub mod commands;
+pub mod data;
+pub mod download;
+pub mod feeds;
+pub mod hooks;
+pub mod m3u;
+pub mod mpv;
+pub mod newsboat;
+pub mod player;
+pub mod probe;
+pub mod settings;
+pub mod tui;
+
+use data::*;
+use feeds::fetch;
+use futures_util::StreamExt;
+pub use settings::{FeedSettings, Settings, Theme};
+
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+#[derive(StructOpt)]
+pub enum AddFeed {
+    #[structopt(about = "Add a youtube channel feed")]
+    Youtube {
+        #[structopt(short = "i", long = "id", help = "Fetch using the channel id")]
+        channel_id: Option<String>,
+        channel_name: String,
+    },
+    #[structopt(about = "Add a query of the German public broadcast multimedia library")]
+    Mediathek {
+        #[structopt(
+            short = "t",
+            long = "title",
+            help = "Assign a title separate from the query"
+        )]
+        title: Option<String>,
+        #[structopt(
+            short = "c",
+            long = "channel",
+            help = "Only broadcasts of this channel"
+        )]
+        channel: Option<String>,
+        #[structopt(long = "topic", help = "Only broadcasts with this topic")]
+        topic: Option<String>,
+        #[structopt(long = "min-duration", help = "Minimum duration in minutes")]
+        min_duration: Option<u64>,
+        #[structopt(
+            long = "future",
+            help = "Include broadcasts that are not available yet"
+        )]
+        future: bool,
+        #[structopt(help = "Searched in titles and topics")]
+        query: String,
+    },
+    #[structopt(about = "Add a custom feed via URL")]
+    Other {
+        #[structopt(
+            short = "t",
+            long = "title",
+            help = "Assign a title other than the URL"
+        )]
+        title: Option<String>,
+        url: String,
+        #[structopt(flatten)]
+        auth: AuthArgs,
+    },
+    #[structopt(about = "Add a local directory whose media files are added as videos")]
+    Directory {
+        #[structopt(
+            short = "t",
+            long = "title",
+            help = "Assign a title other than the name of the directory"
+        )]
+        title: Option<String>,
+        path: String,
+    },
+}
+
+/// Credentials of a (private) feed.
+#[derive(StructOpt, Clone, Default)]
+pub struct AuthArgs {
+    #[structopt(
+        long = "user",
+        conflicts_with = "header",
+        help = "User name for HTTP basic auth"
+    )]
+    pub user: Option<String>,
+    #[structopt(
+        long = "header",
+        help = "Send the secret in this header instead, e.g. Authorization"
+    )]
+    pub header: Option<String>,
+    #[structopt(
+        long = "secret",
+        conflicts_with = "secret-file",
+        help = "Password or header value"
+    )]
+    pub secret: Option<String>,
+    #[structopt(
+        long = "secret-file",
+        help = "Read the password or header value from this file on every fetch"
+    )]
+    pub secret_file: Option<String>,
+}
+
+impl AuthArgs {
+    /// The credentials, if a user or header has been given.
+    pub fn auth(&self) -> Option<FeedAuth> {
+        let kind = match (&self.user, &self.header) {
+            (Some(user), _) => AuthKind::Basic { user: user.clone() },
+            (None, Some(name)) => AuthKind::Header { name: name.clone() },
+            (None, None) => return None,
+        };
+        let secret = match (&self.secret, &self.secret_file) {
+            (_, Some(path)) => Secret::File(path.clone()),
+            (secret, None) => Secret::Value(secret.clone().unwrap_or_default()),
+        };
+        Some(FeedAuth { kind, secret })
+    }
+}
+
+impl AddFeed {
+    /// The credentials for fetching the feed, if any.
+    pub fn auth(&self) -> Option<FeedAuth> {
+        match self {
+            AddFeed::Other { auth, .. } => auth.auth(),
+            _ => None,
+        }
+    }
+}
+
+pub fn youtube_url_user(channel: &str) -> String {
+    format!("https://www.youtube.com/feeds/videos.xml?user={}", channel)
+}
+pub fn youtube_url_channelid(channel: &str) -> String {
+    format!(
+        "https://www.youtube.com/feeds/videos.xml?channel_id={}",
+        channel
+    )
+}
+
+/// The url of the rss feed of MediathekViewWeb. New feeds use the json api instead, see
+/// `feeds::MediathekQuery`.
+pub fn mediathek_url(channel: &str) -> String {
+    format!("https://mediathekviewweb.de/feed?query={}", channel)
+}
+
+impl From<AddFeed> for Feed {
+    fn from(add: AddFeed) -> Self {
+        match add {
+            AddFeed::Youtube {
+                channel_name,
+                channel_id,
+            } => {
+                let url = if let Some(channel_id) = channel_id {
+                    youtube_url_channelid(&channel_id)
+                } else {
+                    youtube_url_user(&channel_name)
+                };
+                Feed {
+                    title: channel_name,
+                    url,
+                    lastupdate: None,
+                }
+            }
+            AddFeed::Mediathek {
+                title,
+                channel,
+                topic,
+                min_duration,
+                future,
+                query,
+            } => {
+                let url = feeds::MediathekQuery {
+                    query: query.clone(),
+                    channel,
+                    topic,
+                    min_duration_secs: min_duration.map(|m| m * 60),
+                    future,
+                }
+                .to_url();
+                Feed {
+                    title: if let Some(title) = title {
+                        title
+                    } else {
+                        query
+                    },
+                    url,
+                    lastupdate: None,
+                }
+            }
+            AddFeed::Other { title, url, .. } => Feed {
+                title: if let Some(title) = title {
+                    title
+                } else {
+                    url.clone()
+                },
+                url,
+                lastupdate: None,
+            },
+            AddFeed::Directory { title, path } => {
+                let path = feeds::local_path(&path).unwrap_or_else(|| path.into());
+                Feed {
+                    title: if let Some(title) = title {
+                        title
+                    } else {
+                        path.file_name()
+                            .unwrap_or(path.as_os_str())
+                            .to_string_lossy()
+                            .into_owned()
+                    },
+                    url: path.to_string_lossy().into_owned(),
+                    lastupdate: None,
+                }
+            }
+        }
+    }
+}
+
+/// The name of this machine, e.g. to tell where a video has been watched.
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_owned())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown host".to_owned())
+}
+
+/// Format a position or duration as "m:ss" or, from an hour on, "h:mm:ss".
+pub fn format_secs(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Open the database configured in `settings` and create missing tables.
+pub fn open_database(settings: &Settings) -> Result<Connection, Error> {
+    let conn = Connection::open(&settings.database_file)?;
+    init_db(&conn)?;
+    Ok(conn)
+}
+
+pub(crate) fn ignore_constraint_errors(
+    res: Result<(), rusqlite::Error>,
+) -> Result<(), rusqlite::Error> {
+    match res {
+        Err(rusqlite::Error::SqliteFailure(error, _))
+            if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            Ok(())
+        }
+        o => o,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Request failed: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("Invalid RSS feed: {0}")]
+    RSS(#[from] rss::Error),
+    #[error("Invalid Atom feed: {0}")]
+    Atom(#[from] atom_syndication::Error),
+    #[error("Database error: {0}")]
+    DB(#[from] rusqlite::Error),
+    #[error("Invalid configuration: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("{0}")]
+    Player(String),
+    #[error("Communication with mpv failed: {0}")]
+    Ipc(mpvipc::Error),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid backup: {0}")]
+    Backup(String),
+    #[error("Invalid proxy {0}")]
+    Proxy(String),
+    #[error("Invalid MediathekViewWeb response: {0}")]
+    Mediathek(String),
+    #[error("No feed with url or title '{0}'")]
+    UnknownFeed(String),
+    #[error("No removed video with id {0}")]
+    UnknownDeleted(i64),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Refreshing {0} feed(s) failed")]
+    RefreshFailed(usize),
+    /// An error that occurred while handling the feed with the url.
+    #[error("Feed {url}: {source}")]
+    Feed {
+        url: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    pub fn in_feed(self, url: &str) -> Self {
+        Error::Feed {
+            url: url.to_owned(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The exit code of the cli for the error:
+    ///
+    /// * 1: Other errors, e.g. failed playback
+    /// * 2: Invalid configuration
+    /// * 3: Database errors
+    /// * 4: Fetching or parsing a feed failed
+    /// * 5: A feed, video or removed video has not been found
+    ///
+    /// Invalid arguments also result in 1, a successful run in 0.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::DB(_) => 3,
+            Error::Reqwest(_)
+            | Error::RSS(_)
+            | Error::Atom(_)
+            | Error::Mediathek(_)
+            | Error::Proxy(_)
+            | Error::Feed { .. }
+            | Error::RefreshFailed(_) => 4,
+            Error::UnknownFeed(_) | Error::UnknownDeleted(_) | Error::NotFound(_) => 5,
+            _ => 1,
+        }
+    }
+
+    fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            Error::Reqwest(e) => e.status(),
+            Error::Feed { source, .. } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// Whether the error may go away by trying again, e.g. a timeout or a server error.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Reqwest(e) if e.is_timeout() || e.is_connect() || e.is_body() => true,
+            Error::Feed { source, .. } => source.is_transient(),
+            _ => self.status().map_or(false, |s| {
+                s.is_server_error()
+                    || s == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || s == reqwest::StatusCode::REQUEST_TIMEOUT
+            }),
+        }
+    }
+
+    /// Whether the feed does not exist (anymore), i.e., the server responded with 404 or 410.
+    pub fn is_permanent(&self) -> bool {
+        self.status().map_or(false, |s| {
+            s == reqwest::StatusCode::NOT_FOUND || s == reqwest::StatusCode::GONE
+        })
+    }
+}
+
+impl From<mpvipc::Error> for Error {
+    fn from(error: mpvipc::Error) -> Self {
+        Error::Ipc(error)
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(value: std::num::ParseIntError) -> Self {
+        Error::Config(config::ConfigError::Foreign(Box::new(value)))
+    }
+}
+
+/// The outcome of refreshing a single feed.
+pub struct FeedReport {
+    pub title: String,
+    pub url: String,
+    /// Number of entries in the fetched feed.
+    pub fetched: usize,
+    /// Number of entries that have been added to the available videos.
+    pub new_entries: usize,
+    /// Number of new entries that have been skipped because of the blocklist.
+    pub blocked: usize,
+    pub error: Option<Error>,
+}
+
+/// The outcome of a refresh, one report per feed.
+#[derive(Default)]
+pub struct RefreshReport {
+    pub feeds: Vec<FeedReport>,
+}
+
+impl RefreshReport {
+    pub fn new_entries(&self) -> usize {
+        self.feeds.iter().map(|f| f.new_entries).sum()
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &FeedReport> {
+        self.feeds.iter().filter(|f| f.error.is_some())
+    }
+
+    /// A one-line summary, e.g. for a status line.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Refreshed {} feeds, {} new videos",
+            self.feeds.len(),
+            self.new_entries()
+        );
+        let (gone, failed): (Vec<_>, Vec<_>) = self
+            .failed()
+            .partition(|f| f.error.as_ref().map_or(false, Error::is_permanent));
+        let titles = |feeds: Vec<&FeedReport>| {
+            feeds
+                .iter()
+                .map(|f| f.title.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !failed.is_empty() {
+            summary.push_str(&format!(", failed: {}", titles(failed)));
+        }
+        if !gone.is_empty() {
+            summary.push_str(&format!(", not found: {}", titles(gone)));
+        }
+        summary
+    }
+}
+
+/// A client for fetching feeds, or an error message if the proxy is invalid.
+fn client(proxy: Option<&str>) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::ClientBuilder::new().timeout(FETCH_TIMEOUT);
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|e| format!("{}: {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Refresh all feeds that have not been fetched within the last `refresh_freshness_secs`.
+pub fn refresh(conn: &Connection, settings: &Settings) -> Result<RefreshReport, rusqlite::Error> {
+    refresh_feeds(conn, stale_feeds(conn, settings)?, settings)
+}
+
+/// The feeds that have not been fetched successfully within the last `refresh_freshness_secs`.
+pub fn stale_feeds(conn: &Connection, settings: &Settings) -> Result<Vec<Feed>, rusqlite::Error> {
+    let feeds = iter_feeds(conn)?;
+    if settings.refresh_freshness_secs == 0 {
+        return Ok(feeds);
+    }
+    let fresh_since =
+        chrono::Local::now() - chrono::Duration::seconds(settings.refresh_freshness_secs as i64);
+    let mut stale = Vec::new();
+    for feed in feeds {
+        if lastfetched(conn, &feed.url)?.map_or(true, |l| l < fresh_since) {
+            stale.push(feed);
+        }
+    }
+    Ok(stale)
+}
+
+pub fn refresh_feeds(
+    conn: &Connection,
+    feeds: Vec<Feed>,
+    settings: &Settings,
+) -> Result<RefreshReport, rusqlite::Error> {
+    refresh_feeds_with_progress(conn, feeds, settings, |_| {})
+}
+
+/// Refresh the feeds like `refresh_feeds` and call `progress` with the report of each feed as
+/// soon as it has been updated.
+pub fn refresh_feeds_with_progress(
+    conn: &Connection,
+    feeds: Vec<Feed>,
+    settings: &Settings,
+    mut progress: impl FnMut(&FeedReport),
+) -> Result<RefreshReport, rusqlite::Error> {
+    if settings.hooks.pre_refresh(&feeds).veto {
+        return Ok(RefreshReport::default());
+    }
+    // Feeds with the same proxy share a client.
+    let mut clients = std::collections::HashMap::new();
+    for feed in &feeds {
+        let proxy = settings.feed_proxy(feed);
+        clients.entry(proxy).or_insert_with(|| client(proxy));
+    }
+    let mut auths = Vec::new();
+    for feed in &feeds {
+        auths.push(feed_auth(conn, &feed.url)?);
+    }
+    let mut fetches = feeds
+        .into_iter()
+        .zip(auths)
+        .map(|(feed, auth)| {
+            let client = &clients[&settings.feed_proxy(&feed)];
+            async move {
+                let client = match client {
+                    Ok(client) => client,
+                    Err(e) => return (Err(Error::Proxy(e.clone())), feed),
+                };
+                let url = settings.fetch_url(&feed.url);
+                let mut delay = std::time::Duration::from_secs_f64(settings.fetch_retry_delay_secs);
+                let mut retries = 0;
+                let fetch_result = loop {
+                    match fetch(client, &url, auth.as_ref()).await {
+                        Err(e) if e.is_transient() && retries < settings.fetch_retries => {
+                            tokio::time::delay_for(delay).await;
+                            delay *= 2;
+                            retries += 1;
+                        }
+                        res => break res,
+                    }
+                };
+                (fetch_result, feed)
+            }
+        })
+        .collect::<futures_util::stream::FuturesUnordered<_>>();
+    let mut rt = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_io()
+        .enable_time()
+        .build()
+        .unwrap();
+    let mut report = RefreshReport::default();
+    // Feeds are updated in the order their fetches complete.
+    rt.block_on(async {
+        while let Some((fetch_result, feed)) = fetches.next().await {
+            let feed_report = update_feed(conn, settings, feed, fetch_result)?;
+            progress(&feed_report);
+            report.feeds.push(feed_report);
+        }
+        Ok::<_, rusqlite::Error>(())
+    })?;
+    settings.hooks.on_refresh_complete(&report);
+    Ok(report)
+}
+
+/// Add the new entries of the fetched feed to the available videos.
+/// What `update_feed` does with an entry that has not been seen before.
+enum NewEntry {
+    /// Add it to the available videos.
+    Add,
+    /// Only record that the feed carries the video, which is already available.
+    Link,
+    /// Skip it because its title matches the blocklist.
+    Blocked,
+}
+
+fn update_feed(
+    conn: &Connection,
+    settings: &Settings,
+    feed: Feed,
+    fetch_result: Result<feeds::FeedEntries, Error>,
+) -> Result<FeedReport, rusqlite::Error> {
+    let fetched_feed = match fetch_result {
+        Ok(feed) => feed,
+        Err(e) => {
+            set_feed_error(conn, &feed.url, Some(&e.to_string()))?;
+            return Ok(FeedReport {
+                title: feed.title,
+                url: feed.url,
+                fetched: 0,
+                new_entries: 0,
+                blocked: 0,
+                error: Some(e),
+            });
+        }
+    };
+    let entries = fetched_feed.entries();
+    let fetched = entries.len();
+    let feed_settings = settings
+        .feed(&feed.title)
+        .or_else(|| settings.feed(&feed.url));
+    let auto_download = feed_settings.map_or(false, |f| f.auto_download);
+    let auto_activate = feed_settings.map_or(false, |f| f.auto_activate);
+    // Feeds without seen entries have last been refreshed by an older version, which only added
+    // entries published after the last update.
+    let legacy = !has_seen_entries(conn, &feed.url)?;
+    // What happens to the entries is decided before the transaction, since the on_add hook runs
+    // external scripts that would hold the lock of the database meanwhile.
+    let mut decisions = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        entry.url = settings.canonical_url(&entry.url);
+        let seen = is_seen(conn, &feed.url, entry.id())?;
+        let is_new = !seen
+            && (!legacy
+                || feed.lastupdate.is_none()
+                || feed.lastupdate.unwrap() < entry.publication)
+            // Active videos are not made available again, e.g., by a full refresh.
+            && find_in_active(conn, &entry.url)?.is_none();
+        let action = if !is_new {
+            None
+        } else if find_in_available(conn, &entry.url)?.is_some() {
+            Some(NewEntry::Link)
+        } else if settings.is_blocked(&entry.title) {
+            Some(NewEntry::Blocked)
+        } else if settings.skip_short(&feed, &entry) || settings.skip_duration(&feed, &entry) {
+            None
+        } else {
+            let response = settings
+                .hooks
+                .on_add(Some(&feed), hooks::entry_json(&entry));
+            if let Some(title) = response.title {
+                entry.title = title;
+            }
+            if response.veto {
+                None
+            } else {
+                Some(NewEntry::Add)
+            }
+        };
+        decisions.push((entry, seen, action));
+    }
+    // All changes of the feed are applied at once, which is also much faster for feeds with many
+    // new entries. Hooks that are not needed for the changes run after the transaction.
+    let (new_entries, blocked) = in_transaction(conn, || -> Result<_, rusqlite::Error> {
+        let mut lastpublication = feed.lastupdate;
+        let mut new_entries = Vec::new();
+        let mut blocked = 0;
+        for (entry, seen, action) in decisions {
+            if seen && entry.guid.is_some() {
+                update_available_entry(conn, &feed.url, &entry)?;
+            }
+            mark_seen(conn, &feed.url, entry.id())?;
+            match action {
+                // Videos that are already available (e.g., from another feed, again in a full
+                // refresh or twice in this feed) stay attributed to their feed, but the link to
+                // this feed is recorded.
+                Some(NewEntry::Link) => add_feed_of_entry(conn, &entry.url, &feed.url)?,
+                Some(NewEntry::Add) if find_in_available(conn, &entry.url)?.is_some() => {
+                    add_feed_of_entry(conn, &entry.url, &feed.url)?
+                }
+                Some(NewEntry::Add) => {
+                    add_entry_to_available(conn, feed.url.clone(), &entry)?;
+                    add_feed_of_entry(conn, &entry.url, &feed.url)?;
+                    if auto_activate {
+                        make_active(conn, &entry.url)?;
+                        move_to_front(conn, &entry.url)?;
+                    }
+                    if auto_download {
+                        enqueue_download(conn, &entry.url, Some(&entry.title))?;
+                    }
+                    new_entries.push(entry.clone());
+                }
+                Some(NewEntry::Blocked) => blocked += 1,
+                None => {}
+            }
+            lastpublication = if let Some(lastpublication) = lastpublication {
+                Some(entry.publication.max(lastpublication))
+            } else {
+                Some(entry.publication)
+            }
+        }
+        if let Some(lastpublication) = lastpublication {
+            set_lastupdate(conn, &feed.url, &lastpublication)?;
+        }
+        set_feed_error(conn, &feed.url, None)?;
+        set_lastfetched(conn, &feed.url, &chrono::Local::now().into())?;
+        Ok((new_entries, blocked))
+    })?;
+    for entry in &new_entries {
+        settings.hooks.on_new_entry(&feed, entry);
+    }
+    settings.hooks.post_refresh(&feed, &new_entries);
+    Ok(FeedReport {
+        title: feed.title,
+        url: feed.url,
+        fetched,
+        new_entries: new_entries.len(),
+        blocked,
+        error: None,
+    })
+}
+
+/// Durations of the startup phases, printed when running with `--profile-startup`.
+pub struct StartupProfile {
+    enabled: bool,
+    last: std::time::Instant,
+    phases: Vec<(&'static str, std::time::Duration)>,
+}
+
+impl StartupProfile {
+    pub fn new(enabled: bool) -> Self {
+        StartupProfile {
+            enabled,
+            last: std::time::Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Record the time since the end of the previous phase.
+    pub fn phase_done(&mut self, phase: &'static str) {
+        if self.enabled {
+            let now = std::time::Instant::now();
+            self.phases.push((phase, now - self.last));
+            self.last = now;
+        }
+    }
+
+    pub fn print(&self) {
+        for (phase, duration) in &self.phases {
+            eprintln!("{:>12}: {:.1}ms", phase, duration.as_secs_f64() * 1000.0);
+        }
+    }
+}
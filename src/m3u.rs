@@ -0,0 +1,65 @@
+//! Export of active and available videos as an extended m3u playlist (`.m3u8`, utf-8) for other
+//! players.
+//!
+//! Each video is written as an `#EXTINF` line with its duration (-1 if unknown) and title,
+//! followed by its url. Active videos come first, in the order of the active list, followed by
+//! the available videos, newest first.
+use crate::data::{find_feed, query_active, query_available, ListQuery};
+use crate::settings::Settings;
+use crate::Error;
+use rusqlite::Connection;
+use std::io::Write;
+
+/// Which videos are exported.
+pub struct Selection {
+    pub active: bool,
+    pub available: bool,
+    /// Url or title of the feed of the videos.
+    pub feed: Option<String>,
+}
+
+fn write_entry(
+    out: &mut impl Write,
+    title: &str,
+    url: &str,
+    duration_secs: Option<f64>,
+) -> std::io::Result<()> {
+    let duration = duration_secs.map_or(-1, |secs| secs.round() as i64);
+    // Line breaks would end the title early.
+    let title = title.replace(|c| c == '\n' || c == '\r', " ");
+    writeln!(out, "#EXTINF:{},{}", duration, title)?;
+    writeln!(out, "{}", url)
+}
+
+/// Write the selected videos to `out` and return how many have been written.
+pub fn export(
+    conn: &Connection,
+    settings: &Settings,
+    selection: &Selection,
+    out: &mut impl Write,
+) -> Result<usize, Error> {
+    if let Some(feed) = &selection.feed {
+        find_feed(conn, feed)?.ok_or_else(|| Error::UnknownFeed(feed.clone()))?;
+    }
+    let query = ListQuery {
+        feed: selection.feed.clone(),
+        ..Default::default()
+    };
+    let mut count = 0;
+    writeln!(out, "#EXTM3U")?;
+    if selection.active {
+        for entry in query_active(conn, &query, settings.active_order)? {
+            let title = entry.title.as_deref().unwrap_or(&entry.url);
+            write_entry(out, title, &entry.url, entry.duration_secs)?;
+            count += 1;
+        }
+    }
+    if selection.available {
+        for entry in query_available(conn, &query)? {
+            write_entry(out, &entry.title, &entry.url, entry.duration_secs)?;
+            count += 1;
+        }
+    }
+    out.flush()?;
+    Ok(count)
+}
@@ -1,29 +1,15 @@
-use atom_syndication;
-use reqwest;
-use rss;
-use rusqlite::{params, Connection};
-use std::{
-    convert::{TryFrom, TryInto},
-    iter::FromIterator,
-    path::{Path, PathBuf},
-};
+use std::path::PathBuf;
 use structopt::StructOpt;
-use unsegen::base::Color;
-
-mod data;
-mod feeds;
-mod mpv;
-mod tui;
 
-use data::*;
-use feeds::fetch;
+mod table;
+use table::{Column, Table};
 
-const DB_NAME: &'static str = "uvp.db";
-const CONFIG_FILE_NAME: &'static str = "uvp.toml";
-const DB_FILE_CONFIG_KEY: &'static str = "database_file";
-const MPV_BINARY_CONFIG_KEY: &'static str = "mpv_binary";
-const THEME_CONFIG_KEY: &'static str = "theme";
-const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+use uvp::data::*;
+use uvp::feeds::normalize_url;
+use uvp::{
+    backup, commands, download, format_secs, m3u, newsboat, player, probe,
+    refresh_feeds_with_progress, tui, AddFeed, AuthArgs, Error, StartupProfile,
+};
 
 #[derive(StructOpt)]
 enum Add {
@@ -35,44 +21,173 @@ enum Add {
 
 #[derive(StructOpt)]
 struct AddVideo {
-    #[structopt(help = "Url")]
-    url: String,
+    #[structopt(
+        help = "Url or path of a local file or directory, - to read a list of urls from stdin",
+        required_unless = "from-file"
+    )]
+    url: Option<String>,
+    #[structopt(
+        long = "from-file",
+        parse(from_os_str),
+        conflicts_with = "url",
+        help = "Add the urls in this file (one per line)"
+    )]
+    from_file: Option<PathBuf>,
+}
+
+#[derive(StructOpt)]
+struct Play {
+    #[structopt(help = "url or path", required_unless = "random")]
+    url: Option<String>,
+    #[structopt(
+        long = "random",
+        conflicts_with = "url",
+        help = "Play a random available video"
+    )]
+    random: bool,
+    #[structopt(
+        short = "f",
+        long = "feed",
+        requires = "random",
+        help = "Only consider videos of the feed with this url or title (with --random)"
+    )]
+    feed: Option<String>,
+    #[structopt(
+        long = "force",
+        help = "Play the video even if another instance of uvp is playing it"
+    )]
+    force: bool,
+    #[structopt(last = true, help = "Additional arguments for the player")]
+    args: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct Resume {
+    #[structopt(
+        long = "force",
+        help = "Play the video even if another instance of uvp is playing it"
+    )]
+    force: bool,
+    #[structopt(last = true, help = "Additional arguments for the player")]
+    args: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct Next {
+    #[structopt(
+        short = "f",
+        long = "feed",
+        help = "Only consider videos of the feed with this url or title"
+    )]
+    feed: Option<String>,
+    #[structopt(long = "oldest", help = "Play the oldest video (default)")]
+    oldest: bool,
+    #[structopt(
+        long = "newest",
+        conflicts_with = "oldest",
+        help = "Play the newest video"
+    )]
+    newest: bool,
+    #[structopt(
+        long = "force",
+        help = "Play the video even if another instance of uvp is playing it"
+    )]
+    force: bool,
+    #[structopt(last = true, help = "Additional arguments for the player")]
+    args: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct Pick {
+    #[structopt(long = "active", help = "Only offer active videos")]
+    active: bool,
+    #[structopt(
+        long = "available",
+        conflicts_with = "active",
+        help = "Only offer available videos"
+    )]
+    available: bool,
+    #[structopt(
+        long = "force",
+        help = "Play the video even if another instance of uvp is playing it"
+    )]
+    force: bool,
+    #[structopt(last = true, help = "Additional arguments for the player")]
+    args: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct PlayFeed {
+    #[structopt(help = "Url or title of the feed")]
+    feed: String,
+    #[structopt(last = true, help = "Additional arguments for mpv")]
+    args: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct Auth {
+    #[structopt(help = "Url or title of the feed")]
+    feed: String,
+    #[structopt(
+        long = "clear",
+        conflicts_with_all = &["user", "header"],
+        help = "Remove the credentials"
+    )]
+    clear: bool,
+    #[structopt(flatten)]
+    auth: AuthArgs,
+}
+
+#[derive(StructOpt)]
+enum Prune {
+    #[structopt(about = "Delete old downloads (see download_keep_days and download_max_bytes)")]
+    Downloads,
+    #[structopt(about = "Delete videos and other data of feeds that have been removed")]
+    Orphans,
 }
 
 #[derive(StructOpt)]
-enum AddFeed {
-    #[structopt(about = "Add a youtube channel feed")]
-    Youtube {
-        #[structopt(short = "i", long = "id", help = "Fetch using the channel id")]
-        channel_id: Option<String>,
-        channel_name: String,
+enum Export {
+    #[structopt(about = "Write feeds, available and active videos to a json file")]
+    Backup {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
     },
-    #[structopt(about = "Add a query of the German public broadcast multimedia library")]
-    Mediathek {
+    #[structopt(about = "Write active and available videos to an m3u playlist for other players")]
+    M3u {
+        #[structopt(long = "active", help = "Only active videos")]
+        active: bool,
         #[structopt(
-            short = "t",
-            long = "title",
-            help = "Assign a title separate from the query"
+            long = "available",
+            conflicts_with = "active",
+            help = "Only available videos"
         )]
-        title: Option<String>,
-        query: String,
-    },
-    #[structopt(about = "Add a custom feed via URL")]
-    Other {
+        available: bool,
         #[structopt(
-            short = "t",
-            long = "title",
-            help = "Assign a title other than the URL"
+            short = "f",
+            long = "feed",
+            help = "Only videos of the feed with this url or title"
         )]
-        title: Option<String>,
-        url: String,
+        feed: Option<String>,
+        #[structopt(parse(from_os_str), help = "Playlist file (.m3u8), stdout if omitted")]
+        file: Option<PathBuf>,
     },
 }
 
 #[derive(StructOpt)]
-struct Play {
-    #[structopt(help = "url")]
-    url: String,
+enum Import {
+    #[structopt(about = "Merge a backup written by `uvp export backup` into the database")]
+    Backup {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        #[structopt(long = "replace", help = "Replace the current data instead of merging")]
+        replace: bool,
+    },
+    #[structopt(about = "Add the feeds of a newsboat urls file")]
+    Newsboat {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
 }
 
 #[derive(StructOpt)]
@@ -88,373 +203,637 @@ enum List {
     #[structopt(about = "List feeds")]
     Feeds,
     #[structopt(about = "List available videos")]
-    Available,
+    Available(ListOptions),
     #[structopt(about = "List active videos")]
-    Active,
+    Active(ListOptions),
 }
 
 #[derive(StructOpt)]
-#[structopt(author, about)]
-enum Options {
-    #[structopt(about = "Add a feed or video")]
-    Add(Add),
-    #[structopt(about = "Refresh the list of available videos")]
-    Refresh,
-    #[structopt(about = "List feeds, available or active videos")]
-    List(List),
-    #[structopt(about = "Play an (external) video")]
-    Play(Play),
-    #[structopt(about = "Remove an item from the list of available/active videos")]
-    Remove(Remove),
-    #[structopt(about = "Start an interactive tui for video selection")]
-    Tui,
-}
-
-fn youtube_url_user(channel: &str) -> String {
-    format!("https://www.youtube.com/feeds/videos.xml?user={}", channel)
-}
-fn youtube_url_channelid(channel: &str) -> String {
-    format!(
-        "https://www.youtube.com/feeds/videos.xml?channel_id={}",
-        channel
-    )
-}
-
-fn mediathek_url(channel: &str) -> String {
-    format!("https://mediathekviewweb.de/feed?query={}", channel)
+struct ListOptions {
+    #[structopt(
+        short = "f",
+        long = "feed",
+        help = "Only list videos of the feed with this url or title"
+    )]
+    feed: Option<String>,
+    #[structopt(
+        long = "since",
+        parse(try_from_str = parse_age),
+        help = "Only list videos published (or played) within this time, e.g. 3d or 12h"
+    )]
+    since: Option<chrono::Duration>,
+    #[structopt(short = "n", long = "limit", help = "List at most this many videos")]
+    limit: Option<usize>,
+    #[structopt(
+        long = "sort",
+        possible_values = &["date", "title", "progress"],
+        help = "Order of the videos (progress only applies to active videos)"
+    )]
+    sort: Option<ListOrder>,
 }
 
-fn ignore_constraint_errors(res: Result<(), rusqlite::Error>) -> Result<(), rusqlite::Error> {
-    match res {
-        Err(rusqlite::Error::SqliteFailure(error, _))
-            if error.code == rusqlite::ErrorCode::ConstraintViolation =>
-        {
-            Ok(())
+impl ListOptions {
+    fn query(self) -> ListQuery {
+        ListQuery {
+            feed: self.feed,
+            since: self
+                .since
+                .map(|since| (chrono::Local::now() - since).into()),
+            limit: self.limit,
+            order: self.sort,
         }
-        o => o,
     }
 }
 
-#[derive(Debug)]
-pub enum Error {
-    Reqwest(reqwest::Error),
-    RSS(rss::Error),
-    Atom(atom_syndication::Error),
-    DB(rusqlite::Error),
-    Config(config::ConfigError),
-}
-
-impl From<reqwest::Error> for Error {
-    fn from(error: reqwest::Error) -> Self {
-        Error::Reqwest(error)
-    }
-}
-impl From<rss::Error> for Error {
-    fn from(error: rss::Error) -> Self {
-        Error::RSS(error)
-    }
-}
-impl From<atom_syndication::Error> for Error {
-    fn from(error: atom_syndication::Error) -> Self {
-        Error::Atom(error)
-    }
-}
-impl From<rusqlite::Error> for Error {
-    fn from(error: rusqlite::Error) -> Self {
-        Error::DB(error)
-    }
-}
-impl From<config::ConfigError> for Error {
-    fn from(error: config::ConfigError) -> Self {
-        Error::Config(error)
+/// Ask a yes/no question on the terminal. Without a terminal, the answer is no.
+fn confirm(question: &str) -> bool {
+    if !termion::is_tty(&std::io::stdin()) {
+        return false;
     }
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
 }
 
-impl From<std::num::ParseIntError> for Error {
-    fn from(value: std::num::ParseIntError) -> Self {
-        Error::Config(config::ConfigError::Foreign(Box::new(value)))
-    }
-}
-
-fn refresh(conn: &Connection) -> Result<(), rusqlite::Error> {
-    let client = reqwest::ClientBuilder::new()
-        .timeout(FETCH_TIMEOUT)
-        .build()
-        .unwrap();
-    let fetches =
-        futures_util::future::join_all(iter_feeds(&conn)?.into_iter().map(|feed| async {
-            let fetch_result = fetch(&client, &feed.url).await;
-            (fetch_result, feed)
-        }));
-    let mut rt = tokio::runtime::Builder::new()
-        .basic_scheduler()
-        .enable_io()
-        .enable_time()
-        .build()
-        .unwrap();
-    let fetched_feeds = rt.block_on(fetches);
-    for (fetch_result, feed) in fetched_feeds {
-        let mut lastpublication = feed.lastupdate;
-
-        let fetched_feed = match fetch_result {
-            Ok(feed) => feed,
-            Err(Error::Reqwest(e)) => {
-                eprintln!("Failed to fetch feed {}: {}", feed.title, e);
-                continue;
-            }
-            Err(Error::RSS(e)) => {
-                eprintln!("Failed to parse feed {}: {}", feed.title, e);
-                continue;
-            }
-            Err(Error::Atom(e)) => {
-                eprintln!("Failed to parse feed {}: {}", feed.title, e);
-                continue;
-            }
-            Err(e) => {
-                panic!("Unexpected error during fetch: {:?}", e);
-            }
-        };
-        for entry in fetched_feed.entries() {
-            if feed.lastupdate.is_none() || feed.lastupdate.unwrap() < entry.publication {
-                ignore_constraint_errors(add_entry_to_available(&conn, feed.url.clone(), &entry))?;
-            }
-            lastpublication = if let Some(lastpublication) = lastpublication {
-                Some(entry.publication.max(lastpublication))
+/// Play `url`, asking whether to play it anyway if another instance of uvp is playing it.
+fn play(
+    conn: &rusqlite::Connection,
+    url: &str,
+    settings: &uvp::Settings,
+    args: &[String],
+    force: bool,
+) -> Result<(), Error> {
+    match player::play(conn, url, settings, args, force)? {
+        player::Outcome::Stopped | player::Outcome::Interrupted => Ok(()),
+        player::Outcome::Failed(e) => Err(Error::Player(e)),
+        player::Outcome::InUse(lease) => {
+            let expires = lease.expires.with_timezone(&chrono::Local).format("%H:%M");
+            if confirm(&format!(
+                "{} is being played by {} (until {} at the latest). Play it anyway?",
+                url, lease.holder, expires
+            )) {
+                play(conn, url, settings, args, true)
             } else {
-                Some(entry.publication)
+                Err(Error::Player(format!(
+                    "{} is being played by {} (--force plays it anyway)",
+                    url, lease.holder
+                )))
             }
         }
-        if let Some(lastpublication) = lastpublication {
-            conn.execute(
-                r#"
-                UPDATE feed SET lastupdate = ?1 WHERE feedurl = ?2
-                "#,
-                params!(lastpublication.to_rfc3339(), feed.url),
-            )?;
-        }
     }
-    Ok(())
-}
-
-struct Theme {
-    primary_fg: Color,
-    primary_bg: Color,
-    alt_fg: Color,
-    alt_bg: Color,
 }
 
-impl Default for Theme {
-    fn default() -> Self {
-        Theme {
-            primary_fg: Color::Default,
-            primary_bg: Color::Default,
-            alt_fg: Color::Default,
-            alt_bg: Color::Ansi(8),
-        }
+/// Parse an age like "90m", "12h", "3d" or "2w".
+fn parse_age(s: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("Invalid time '{}' (e.g. 90m, 12h, 3d or 2w)", s);
+    let split = s.len() - s.chars().last().map_or(0, |c| c.len_utf8());
+    let number = s[..split]
+        .parse::<i64>()
+        .ok()
+        .filter(|number| *number > 0)
+        .ok_or_else(invalid)?;
+    match &s[split..] {
+        "m" => Ok(chrono::Duration::minutes(number)),
+        "h" => Ok(chrono::Duration::hours(number)),
+        "d" => Ok(chrono::Duration::days(number)),
+        "w" => Ok(chrono::Duration::weeks(number)),
+        _ => Err(invalid()),
     }
 }
 
-impl Theme {
-    const KEYS: &'static [&'static str] = &["primary_fg", "primary_bg", "alt_fg", "alt_bg"];
+#[derive(StructOpt)]
+#[structopt(author, about)]
+struct Args {
+    #[structopt(
+        long = "profile-startup",
+        help = "Print the time spent in the phases of startup"
+    )]
+    profile_startup: bool,
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        global = true,
+        help = "Only print errors and the requested data"
+    )]
+    quiet: bool,
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        global = true,
+        conflicts_with = "quiet",
+        help = "Also print what has been done"
+    )]
+    verbose: bool,
+    #[structopt(subcommand)]
+    command: Options,
 }
 
-impl TryFrom<config::Map<String, config::Value>> for Theme {
-    type Error = Error;
-
-    fn try_from(value: config::Map<String, config::Value>) -> Result<Self, Self::Error> {
-        let mut theme = Theme::default();
-
-        for key in Self::KEYS {
-            if let Ok(v) = value
-                .get(*key)
-                .ok_or(config::ConfigError::NotFound(key.to_string()))
-                .and_then(|v| v.clone().into_string())
-            {
-                let value = match v.as_str() {
-                    "default" => Color::Default,
-                    _ => Color::Ansi(v.parse::<u8>()?),
-                };
-
-                match *key {
-                    "primary_fg" => theme.primary_fg = value,
-                    "primary_bg" => theme.primary_bg = value,
-                    "alt_fg" => theme.alt_fg = value,
-                    "alt_bg" => theme.alt_bg = value,
-                    _ => continue,
-                }
-            }
-        }
-
-        Ok(theme)
-    }
+#[derive(StructOpt)]
+enum Options {
+    #[structopt(about = "Add a feed or video")]
+    Add(Add),
+    #[structopt(about = "Show or set the credentials for fetching a feed")]
+    Auth(Auth),
+    #[structopt(about = "Refresh the list of available videos")]
+    Refresh {
+        #[structopt(
+            long = "full",
+            help = "Add all entries of the feeds again, including removed ones"
+        )]
+        full: bool,
+        #[structopt(help = "Url or title of the only feed to refresh")]
+        feed: Option<String>,
+    },
+    #[structopt(about = "List feeds, available or active videos")]
+    List(List),
+    #[structopt(about = "Play an (external) video")]
+    Play(Play),
+    #[structopt(about = "Play all available videos of a feed as a playlist")]
+    PlayFeed(PlayFeed),
+    #[structopt(about = "Continue the most recently played video")]
+    Resume(Resume),
+    #[structopt(about = "Play the oldest (or newest) available video")]
+    Next(Next),
+    #[structopt(about = "Select a video to play using a picker like fzf, rofi or dmenu")]
+    Pick(Pick),
+    #[structopt(about = "Download a video or all queued videos")]
+    Download {
+        #[structopt(help = "Url of a video to queue before downloading")]
+        url: Option<String>,
+    },
+    #[structopt(about = "Remove an item from the list of available/active videos")]
+    Remove(Remove),
+    #[structopt(about = "List recently removed videos or restore one of them")]
+    Undelete {
+        #[structopt(help = "Id (see the list) of the video to restore")]
+        id: Option<i64>,
+    },
+    #[structopt(about = "Delete data that is no longer needed")]
+    Prune(Prune),
+    #[structopt(about = "Export data of uvp")]
+    Export(Export),
+    #[structopt(about = "Import data into uvp")]
+    Import(Import),
+    #[structopt(about = "Start an interactive tui for video selection")]
+    Tui,
 }
 
-impl From<Theme> for config::Value {
-    fn from(value: Theme) -> Self {
-        let values = [
-            value.primary_fg,
-            value.primary_bg,
-            value.alt_fg,
-            value.alt_bg,
-        ];
-
-        let map = values.iter().zip(Theme::KEYS).map(|(v, k)| {
-            let color_code = match v {
-                Color::Ansi(n) => n.to_string(),
-                Color::Default => "default".to_string(),
-                _ => unreachable!(),
-            };
-            (
-                (*k).to_owned(),
-                config::Value::new(
-                    Some(&(*k).to_owned()),
-                    config::ValueKind::String(color_code),
-                ),
-            )
-        });
-
-        config::Value::new(
-            Some(&THEME_CONFIG_KEY.to_owned()),
-            config::ValueKind::Table(config::Map::from_iter(map.into_iter())),
-        )
-    }
+/// Number of removed videos listed by `uvp undelete`.
+const UNDELETE_LIST_LENGTH: usize = 20;
+
+/// Replace an alias (see `aliases`) by its command line or, if there is no subcommand, add the
+/// `default_command`. Returns `None` if there is nothing to expand.
+fn expand_alias(settings: &uvp::Settings, mut argv: Vec<String>) -> Option<Vec<String>> {
+    let command = argv
+        .iter()
+        .skip(1)
+        .position(|arg| !arg.starts_with('-'))
+        .map(|i| i + 1);
+    let (index, command_line) = match command {
+        Some(i) => (i, settings.aliases.get(&argv[i])?),
+        None => (argv.len(), settings.default_command.as_ref()?),
+    };
+    let end = (index + 1).min(argv.len());
+    argv.splice(
+        index..end,
+        command_line.split_whitespace().map(|arg| arg.to_owned()),
+    );
+    Some(argv)
 }
 
-fn main() -> Result<(), Error> {
-    let default_db_path = dirs::data_dir()
-        .unwrap_or(Path::new("./").to_owned())
-        .join(DB_NAME);
-
-    let mut settings_builder = config::Config::builder()
-        .set_default(
-            DB_FILE_CONFIG_KEY,
-            default_db_path.to_string_lossy().as_ref(),
-        )?
-        .set_default(MPV_BINARY_CONFIG_KEY, "mpv")?
-        .set_default(THEME_CONFIG_KEY, Theme::default())?;
-
-    for config_location in vec![
-        Some(PathBuf::from("/etc")),
-        Some(PathBuf::from("/usr/etc")),
-        dirs::config_dir(),
-    ] {
-        if let Some(config_location) = config_location {
-            let config_file = config_location.join(CONFIG_FILE_NAME);
-            if config_file.is_file() {
-                settings_builder = settings_builder.add_source(config::File::new(
-                    config_file.to_str().unwrap(),
-                    config::FileFormat::Toml,
-                ));
+fn run() -> Result<(), Error> {
+    // Aliases are only looked up if the arguments are not valid as they are, so they cannot
+    // shadow the builtin subcommands.
+    let (args, settings) = match Args::from_iter_safe(std::env::args()) {
+        Ok(args) => (args, None),
+        Err(e)
+            if e.kind == structopt::clap::ErrorKind::HelpDisplayed
+                || e.kind == structopt::clap::ErrorKind::VersionDisplayed =>
+        {
+            e.exit()
+        }
+        Err(e) => {
+            let settings = uvp::settings::load().unwrap_or_else(|_| e.exit());
+            match expand_alias(&settings, std::env::args().collect()) {
+                Some(argv) => (Args::from_iter(argv), Some(settings)),
+                None => e.exit(),
             }
         }
-    }
-
-    let settings = settings_builder.build()?;
-
-    let db_path = settings.get_string(DB_FILE_CONFIG_KEY).unwrap();
-    let mpv_binary = settings.get_string(MPV_BINARY_CONFIG_KEY).unwrap();
-
-    let theme: Theme = settings.get_table(THEME_CONFIG_KEY)?.try_into()?;
-
-    //let flags = OpenFlags::SQLITE_OPEN_FULL_MUTEX;
-    //let conn = Connection::open_with_flags(db_path, flags).unwrap();
-    let conn = Connection::open(Path::new(&db_path))?;
-    for def in TABLE_DEFINITIONS {
-        conn.execute(def, params![])?;
-    }
-    match Options::from_args() {
-        Options::Add(Add::Video(vid)) => {
-            make_active(&conn, &vid.url)?;
+    };
+    let mut profile = StartupProfile::new(args.profile_startup);
+
+    let settings = match settings {
+        Some(settings) => settings,
+        None => uvp::settings::load()?,
+    };
+    profile.phase_done("config");
+
+    let conn = uvp::open_database(&settings)?;
+    profile.phase_done("database");
+    let quiet = args.quiet;
+    let verbose = args.verbose;
+    // Messages that are left out with --quiet and those that are only printed with --verbose.
+    let info = |msg: String| {
+        if !quiet {
+            println!("{}", msg)
         }
-        Options::Play(p) => {
-            mpv::play(&conn, &p.url, &mpv_binary)?;
+    };
+    let detail = |msg: String| {
+        if verbose {
+            println!("{}", msg)
         }
-        Options::Add(Add::Feed(add)) => {
-            let feed = match add {
-                AddFeed::Youtube {
-                    channel_name,
-                    channel_id,
-                } => {
-                    let url = if let Some(channel_id) = channel_id {
-                        youtube_url_channelid(&channel_id)
+    };
+    match args.command {
+        Options::Add(Add::Video(vid)) => {
+            let urls = match (vid.url, vid.from_file) {
+                (_, Some(file)) => Some(commands::read_url_list(std::io::BufReader::new(
+                    std::fs::File::open(file)?,
+                ))?),
+                (Some(url), None) if url == "-" => {
+                    Some(commands::read_url_list(std::io::stdin().lock())?)
+                }
+                (Some(url), None) => {
+                    if commands::add_video(&conn, &settings, &url)? {
+                        detail(format!("Added {}", url));
                     } else {
-                        youtube_url_user(&channel_name)
-                    };
-                    Feed {
-                        title: channel_name,
-                        url,
-                        lastupdate: None,
+                        detail(format!("Not adding {}, vetoed by the on_add hook", url));
                     }
+                    None
                 }
-                AddFeed::Mediathek { title, query } => {
-                    let url = mediathek_url(&query);
-                    Feed {
-                        title: if let Some(title) = title {
-                            title
-                        } else {
-                            query
-                        },
-                        url,
-                        lastupdate: None,
+                (None, None) => unreachable!("url is required unless --from-file is given"),
+            };
+            if let Some(urls) = urls {
+                let results = commands::add_videos(&conn, &settings, &urls)?;
+                let mut added = 0;
+                for (url, result) in &results {
+                    match result {
+                        commands::AddResult::Added => {
+                            added += 1;
+                            info(format!("Added {}", url));
+                        }
+                        commands::AddResult::AlreadyActive => {
+                            info(format!("Skipped {}, already active", url))
+                        }
+                        commands::AddResult::Vetoed => {
+                            info(format!("Skipped {}, vetoed by the on_add hook", url))
+                        }
                     }
                 }
-                AddFeed::Other { title, url } => Feed {
-                    title: if let Some(title) = title {
-                        title
-                    } else {
-                        url.clone()
-                    },
-                    url,
-                    lastupdate: None,
-                },
+                detail(format!("Added {} of {} videos", added, results.len()));
+            }
+        }
+        Options::Play(p) => {
+            let url = if p.random {
+                if let Some(feed) = &p.feed {
+                    find_feed(&conn, feed)?.ok_or_else(|| Error::UnknownFeed(feed.clone()))?;
+                }
+                random_available(&conn, p.feed.as_deref())?
+                    .ok_or_else(|| Error::NotFound("No available videos".to_owned()))?
+                    .url
+            } else {
+                normalize_url(&p.url.expect("url is required without --random"))
+            };
+            play(&conn, &url, &settings, &p.args, p.force)?;
+        }
+        Options::Resume(r) => {
+            let active = most_recently_played(&conn)?
+                .ok_or_else(|| Error::NotFound("No video has been played yet".to_owned()))?;
+            play(&conn, &active.url, &settings, &r.args, r.force)?;
+        }
+        Options::Next(n) => {
+            let available =
+                commands::next_available(&conn, n.feed.as_deref(), n.newest && !n.oldest)?
+                    .ok_or_else(|| Error::NotFound("No available videos".to_owned()))?;
+            play(&conn, &available.url, &settings, &n.args, n.force)?;
+        }
+        Options::Pick(p) => {
+            let url = match commands::pick(&conn, &settings, !p.available, !p.active)? {
+                Some(url) => url,
+                None => return Ok(()),
             };
+            play(&conn, &url, &settings, &p.args, p.force)?;
+        }
+        Options::PlayFeed(p) => {
+            if let player::Outcome::Failed(e) =
+                player::play_feed(&conn, &p.feed, &settings, &p.args)?
+            {
+                return Err(Error::Player(e));
+            }
+        }
+        Options::Add(Add::Feed(add)) => {
+            let auth = add.auth();
+            let feed: Feed = add.into();
             add_to_feed(&conn, &feed)?;
+            if auth.is_some() {
+                set_feed_auth(&conn, &feed.url, auth.as_ref())?;
+            }
+            detail(format!("Added feed {} ({})", feed.title, feed.url));
+        }
+        Options::Auth(a) => {
+            let feed =
+                find_feed(&conn, &a.feed)?.ok_or_else(|| Error::UnknownFeed(a.feed.clone()))?;
+            if a.clear {
+                set_feed_auth(&conn, &feed.url, None)?;
+                detail(format!("Removed the credentials of {}", feed.title));
+            } else if let Some(auth) = a.auth.auth() {
+                set_feed_auth(&conn, &feed.url, Some(&auth))?;
+                detail(format!("Set the credentials of {}", feed.title));
+            } else {
+                match feed_auth(&conn, &feed.url)? {
+                    Some(auth) => {
+                        let kind = match auth.kind {
+                            AuthKind::Basic { user } => format!("Basic auth as {}", user),
+                            AuthKind::Header { name } => format!("Header {}", name),
+                        };
+                        let secret = match auth.secret {
+                            Secret::Value(_) => "stored secret".to_owned(),
+                            Secret::File(path) => format!("secret from {}", path),
+                        };
+                        println!("{} ({})", kind, secret);
+                    }
+                    None => println!("No credentials"),
+                }
+            }
         }
         Options::List(what) => match what {
             List::Feeds => {
-                println!("{} \t| {} \t| {}", "Title", "Last Update", "Url");
+                let mut table = Table::new(vec![
+                    Column::new("Title").shrink(),
+                    Column::new("Last Update"),
+                    Column::new("Last Fetch"),
+                    Column::new("Url"),
+                    Column::new("Categories").shrink(),
+                    Column::new("Status").shrink(),
+                ]);
                 for feed in iter_feeds(&conn)? {
-                    println!(
-                        "{} \t| {} \t| {}",
+                    let status = match feed_error(&conn, &feed.url)? {
+                        Some(error) => format!(
+                            "Failing since {}: {}",
+                            error.since.to_rfc3339(),
+                            error.message
+                        ),
+                        None => "Ok".to_owned(),
+                    };
+                    let categories = categories_of_feed(&conn, &feed.url)?.join(", ");
+                    table.row(vec![
                         feed.title,
                         feed.lastupdate
                             .map(|lu| lu.to_rfc3339())
                             .unwrap_or("Never".to_owned()),
+                        lastfetched(&conn, &feed.url)?
+                            .map(|lf| lf.to_rfc3339())
+                            .unwrap_or("Never".to_owned()),
                         feed.url,
-                    );
+                        categories,
+                        status,
+                    ]);
                 }
+                table.print();
             }
-            List::Available => {
-                println!("{} \t| {} \t| {}", "Title", "Publication", "Url");
-                for entry in iter_available(&conn)? {
-                    println!(
-                        "{} \t| {} \t| {}",
+            List::Available(options) => {
+                let mut table = Table::new(vec![
+                    Column::new("Title").shrink(),
+                    Column::new("Duration").right(),
+                    Column::new("Publication"),
+                    Column::new("Url"),
+                ]);
+                for entry in query_available(&conn, &options.query())? {
+                    table.row(vec![
                         entry.title,
+                        entry
+                            .duration_secs
+                            .map(format_secs)
+                            .unwrap_or("Unknown".to_owned()),
                         entry.publication.to_rfc3339(),
                         entry.url,
-                    );
+                    ]);
                 }
+                table.print();
             }
-            List::Active => {
-                println!("{} \t| {} \t| {}", "Title", "Url", "Playback");
-                for entry in iter_active(&conn)? {
-                    let title = entry.title.unwrap_or("Unknown".to_string());
-                    println!("{} \t| {} \t {}", title, entry.url, entry.position_secs);
+            List::Active(options) => {
+                let mut table = Table::new(vec![
+                    Column::new("Title").shrink(),
+                    Column::new("Position").right(),
+                    Column::new("Duration").right(),
+                    Column::new("Url"),
+                ]);
+                for entry in query_active(&conn, &options.query(), settings.active_order)? {
+                    table.row(vec![
+                        entry.title.unwrap_or("Unknown".to_string()),
+                        format_secs(entry.position_secs),
+                        entry
+                            .duration_secs
+                            .map(format_secs)
+                            .unwrap_or("Unknown".to_owned()),
+                        entry.url,
+                    ]);
                 }
+                table.print();
             }
         },
         Options::Remove(Remove::Video { url }) => {
+            let url = normalize_url(&url);
+            if let Some(available) = find_in_available(&conn, &url)? {
+                record_deletion(&conn, &DeletedItem::Available(available))?;
+            }
             remove_from_available(&conn, &url)?;
+            detail(format!("Removed {}", url));
         }
         Options::Remove(Remove::Feed { url }) => {
             remove_feed(&conn, &url)?;
+            detail(format!("Removed feed {}", url));
+        }
+        Options::Refresh { full, feed } => {
+            let mut feeds = match feed {
+                Some(feed) => vec![find_feed(&conn, &feed)?.ok_or(Error::UnknownFeed(feed))?],
+                None if full => iter_feeds(&conn)?,
+                None => {
+                    let stale = uvp::stale_feeds(&conn, &settings)?;
+                    let skipped = iter_feeds(&conn)?.len() - stale.len();
+                    if skipped > 0 {
+                        detail(format!("Skipping {} recently fetched feeds", skipped));
+                    }
+                    stale
+                }
+            };
+            if full {
+                for feed in &mut feeds {
+                    forget_entries(&conn, &feed.url)?;
+                    feed.lastupdate = None;
+                }
+            }
+            info(format!(
+                "{} \t| {} \t| {} \t| {}",
+                "Title", "Fetched", "New", "Status"
+            ));
+            let report = refresh_feeds_with_progress(&conn, feeds, &settings, |feed| {
+                info(format!(
+                    "{} \t| {} \t| {} \t| {}",
+                    feed.title,
+                    feed.fetched,
+                    feed.new_entries,
+                    feed.error
+                        .as_ref()
+                        .map(|e| e.to_string())
+                        .unwrap_or("Ok".to_owned()),
+                ));
+                if feed.blocked > 0 {
+                    detail(format!(
+                        "Skipped {} new entries of {} (blocklist)",
+                        feed.blocked, feed.title
+                    ));
+                }
+            })?;
+            profile.phase_done("refresh");
+            download::run_queue(&conn, &settings, |path| {
+                info(format!("Downloaded {}", path))
+            })?;
+            if settings.probe_durations {
+                match probe::run(&conn, &settings) {
+                    Ok(probed) => detail(format!("Found the durations of {} videos", probed)),
+                    // E.g., yt-dlp is not installed, which does not make the refresh fail.
+                    Err(e) => eprintln!("Probing durations failed: {}", e),
+                }
+            }
+            let failed = report.failed().count();
+            if failed > 0 {
+                return Err(Error::RefreshFailed(failed));
+            }
+        }
+        Options::Undelete { id: None } => {
+            let mut table = Table::new(vec![
+                Column::new("Id").right(),
+                Column::new("Removed"),
+                Column::new("List"),
+                Column::new("Title").shrink(),
+                Column::new("Url"),
+            ]);
+            for deleted in iter_deleted(&conn, UNDELETE_LIST_LENGTH)? {
+                table.row(vec![
+                    deleted.id.to_string(),
+                    deleted.deleted.to_rfc3339(),
+                    deleted.item.list().to_owned(),
+                    deleted.item.title().unwrap_or("Unknown").to_owned(),
+                    deleted.item.url().to_owned(),
+                ]);
+            }
+            table.print();
+        }
+        Options::Undelete { id: Some(id) } => {
+            let deleted = find_deleted(&conn, id)?.ok_or(Error::UnknownDeleted(id))?;
+            let list = restore_deleted(&conn, &deleted)?;
+            detail(format!(
+                "Restored {} to the {} videos",
+                deleted.item.url(),
+                list
+            ));
+        }
+        Options::Prune(Prune::Downloads) => {
+            for download in download::prune(&conn, &settings)? {
+                info(format!("Deleted {}", download.path.unwrap_or(download.url)));
+            }
+        }
+        Options::Prune(Prune::Orphans) => {
+            info(format!("Deleted {} rows", remove_orphans(&conn)?));
+        }
+        Options::Export(Export::Backup { file }) => {
+            backup::export_to_file(&conn, &file)?;
+        }
+        Options::Export(Export::M3u {
+            active,
+            available,
+            feed,
+            file,
+        }) => {
+            let selection = m3u::Selection {
+                active: !available,
+                available: !active,
+                feed,
+            };
+            match file {
+                Some(file) => {
+                    let mut out = std::io::BufWriter::new(std::fs::File::create(&file)?);
+                    let count = m3u::export(&conn, &settings, &selection, &mut out)?;
+                    detail(format!("Exported {} videos to {}", count, file.display()));
+                }
+                None => {
+                    m3u::export(&conn, &settings, &selection, &mut std::io::stdout().lock())?;
+                }
+            }
+        }
+        Options::Import(Import::Backup { file, replace }) => {
+            backup::import_from_file(&conn, &file, replace)?;
+        }
+        Options::Import(Import::Newsboat { file }) => {
+            let count = newsboat::import(&conn, &file)?;
+            info(format!("Imported {} feeds", count));
         }
-        Options::Refresh => {
-            refresh(&conn)?;
+        Options::Download { url } => {
+            if let Some(url) = url {
+                commands::queue_download(&conn, &url)?;
+            }
+            download::run_queue(&conn, &settings, |path| {
+                info(format!("Downloaded {}", path))
+            })?;
         }
         Options::Tui => {
-            tui::run(&conn, &mpv_binary, &theme)?;
+            tui::run(&conn, &settings, &mut profile)?;
         }
     }
+    profile.print();
     Ok(())
 }
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn settings() -> uvp::Settings {
+        let mut settings = uvp::Settings::default();
+        settings
+            .aliases
+            .insert("u".to_owned(), "refresh --full".to_owned());
+        settings
+    }
+
+    #[test]
+    fn expand_alias_after_flags() {
+        assert_eq!(
+            expand_alias(&settings(), argv(&["uvp", "-q", "u", "-f", "x"])),
+            Some(argv(&["uvp", "-q", "refresh", "--full", "-f", "x"]))
+        );
+        assert_eq!(expand_alias(&settings(), argv(&["uvp", "-q", "x"])), None);
+    }
+
+    #[test]
+    fn expand_default_command() {
+        let mut settings = settings();
+        assert_eq!(expand_alias(&settings, argv(&["uvp", "-q"])), None);
+        settings.default_command = Some("list active".to_owned());
+        assert_eq!(
+            expand_alias(&settings, argv(&["uvp", "-q"])),
+            Some(argv(&["uvp", "-q", "list", "active"]))
+        );
+    }
+
+    #[test]
+    fn parse_ages() {
+        assert_eq!(parse_age("90m"), Ok(chrono::Duration::minutes(90)));
+        assert_eq!(parse_age("3d"), Ok(chrono::Duration::days(3)));
+        assert_eq!(parse_age("2w"), Ok(chrono::Duration::weeks(2)));
+        for age in &["", "m", "0h", "-1d", "12", "12x", "1ä", "ä"] {
+            assert!(parse_age(age).is_err(), "{}", age);
+        }
+    }
+}
@@ -1,77 +1,493 @@
-use crate::data::{
-    find_in_active, make_active, remove_from_active, set_duration, set_position_secs, set_title,
-};
-use rusqlite::Connection;
-
-const END_DETECTION_TOLERANCE_SECONDS: f64 = 1.0;
-
-pub fn play(conn: &Connection, url: &str, mpv_binary: &str) -> Result<(), rusqlite::Error> {
-    crate::ignore_constraint_errors(make_active(conn, url))?;
-    let active = find_in_active(conn, url)?.unwrap();
-
-    let tmp_dir = tempfile::tempdir().unwrap();
-
-    let pipe_path = tmp_dir.path().join("mpv.pipe");
-
-    let mut output = std::process::Command::new(mpv_binary)
-        .arg(&active.url)
-        .arg(format!(
-            "--input-ipc-server={}",
-            pipe_path.to_string_lossy()
-        ))
-        .arg(format!("--start=+{}", active.position_secs))
-        .arg("--force-window=immediate")
-        .spawn()
-        .unwrap();
-    while !pipe_path.exists() {
-        std::thread::sleep(std::time::Duration::from_millis(100));
+use crate::data::Active;
+use crate::player::{Playback, Player};
+use crate::{Error, Settings};
+use signal_hook::iterator::Signals;
+
+/// How long to wait for mpv to open its ipc socket.
+const IPC_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Translate a quality like "720p" into a ytdl format. Anything else is used as a format as is.
+pub(crate) fn ytdl_format(quality: &str) -> String {
+    match quality.strip_suffix('p').map(|h| h.parse::<u32>()) {
+        Some(Ok(height)) => format!(
+            "bestvideo[height<=?{0}]+bestaudio/best[height<=?{0}]",
+            height
+        ),
+        _ => quality.to_owned(),
+    }
+}
+
+fn observe_properties(mpv: &mut mpvipc::Mpv) -> Result<(), mpvipc::Error> {
+    mpv.observe_property(0, "playback-time")?;
+    mpv.observe_property(1, "duration")?;
+    mpv.observe_property(2, "media-title")?;
+    mpv.observe_property(3, "chapter")?;
+    mpv.observe_property(4, "chapter-list")?;
+    mpv.observe_property(5, "path")?;
+    Ok(())
+}
+
+/// Events of a playlist passed to the callback of `Mpv::play_playlist`.
+pub enum PlaylistEvent<'a> {
+    Started(&'a Active),
+    Progress(&'a Active, f64),
+    Ended(&'a Active, Playback),
+}
+
+/// Asks mpv for the position when uvp receives SIGTERM/SIGINT (e.g., on system shutdown), so
+/// that it can be saved before uvp exits.
+struct SignalCheckpoint {
+    signals: Signals,
+    handler: std::thread::JoinHandle<()>,
+    receiver: std::sync::mpsc::Receiver<Option<f64>>,
+}
+
+impl SignalCheckpoint {
+    /// On a signal, `on_signal` is called with a second ipc connection to the mpv at `socket`. It
+    /// returns the position and has to make the event loop of the first connection end.
+    fn new(
+        socket: std::path::PathBuf,
+        on_signal: impl FnOnce(&mpvipc::Mpv) -> Option<f64> + Send + 'static,
+    ) -> Result<Self, Error> {
+        let signals = Signals::new(&[signal_hook::SIGTERM, signal_hook::SIGINT])?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let handler = {
+            let signals = signals.clone();
+            std::thread::spawn(move || {
+                if signals.forever().next().is_some() {
+                    let position = mpvipc::Mpv::connect(socket.to_str().unwrap())
+                        .ok()
+                        .and_then(|mpv| on_signal(&mpv));
+                    let _ = sender.send(position);
+                }
+            })
+        };
+        Ok(SignalCheckpoint {
+            signals,
+            handler,
+            receiver,
+        })
+    }
+
+    /// Stop listening for signals. Returns the position at the time uvp received SIGTERM/SIGINT
+    /// (if it did).
+    fn finish(self) -> Option<Option<f64>> {
+        self.signals.close();
+        self.handler.join().unwrap();
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// A running mpv process with an ipc connection.
+struct Session {
+    child: std::process::Child,
+    ipc: mpvipc::Mpv,
+    checkpoint: SignalCheckpoint,
+    _tmp_dir: tempfile::TempDir,
+}
+
+impl Session {
+    /// Stop listening for signals and wait for mpv to exit. Returns the position at the time
+    /// uvp received SIGTERM/SIGINT (if it did) and an error message if mpv failed.
+    fn finish(self) -> Result<(Option<Option<f64>>, Option<String>), Error> {
+        let Session {
+            mut child,
+            checkpoint,
+            ..
+        } = self;
+        let checkpoint = checkpoint.finish();
+        let status = child.wait()?;
+        let error = if !status.success() && checkpoint.is_none() {
+            Some(format!("mpv exited with {}", status))
+        } else {
+            None
+        };
+        Ok((checkpoint, error))
+    }
+}
+
+/// Apply a change of one of the observed properties (except for the path) to `playback`.
+/// Returns the new position if it changed.
+fn update_playback(playback: &mut Playback, property: mpvipc::Property) -> Option<f64> {
+    match property {
+        mpvipc::Property::PlaybackTime(Some(t)) => {
+            playback.position_secs = Some(t);
+            return Some(t);
+        }
+        mpvipc::Property::Duration(Some(d)) => {
+            playback.duration_secs = Some(d);
+        }
+        mpvipc::Property::Unknown {
+            name,
+            data: mpvipc::MpvDataType::String(t),
+        } if name == "media-title" => {
+            playback.title = Some(t);
+        }
+        mpvipc::Property::Chapter(c) => {
+            playback.chapter = c.map(|c| c as i64);
+        }
+        mpvipc::Property::Unknown {
+            name,
+            data: mpvipc::MpvDataType::Array(chapters),
+        } if name == "chapter-list" => {
+            playback.chapters = chapters
+                .iter()
+                .filter_map(|chapter| match chapter {
+                    mpvipc::MpvDataType::HashMap(chapter) => match chapter.get("time") {
+                        Some(mpvipc::MpvDataType::Double(t)) => Some(*t),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+        }
+        _ => {}
+    }
+    None
+}
+
+/// mpv, controlled via its json ipc interface.
+pub struct Mpv<'a> {
+    settings: &'a Settings,
+}
+
+impl<'a> Mpv<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Mpv { settings }
+    }
+
+    /// Start mpv for the given urls and connect to it. With `shared`, mpv listens on the fixed
+    /// socket of `reuse_mpv`, which must only be set once connecting to it has failed. Failures
+    /// to start mpv are returned as `Ok(Err(message))`.
+    fn start(
+        &self,
+        urls: &[&str],
+        feed_title: Option<&str>,
+        start_secs: f64,
+        args: &[String],
+        shared: bool,
+    ) -> Result<Result<Session, String>, Error> {
+        let settings = self.settings;
+        let tmp_dir = tempfile::tempdir()?;
+
+        // Other instances of uvp append their videos via the fixed socket (see `enqueue`).
+        let pipe_path = if shared {
+            // Left behind by an mpv that has crashed (connecting to it has failed).
+            let _ = std::fs::remove_file(&settings.mpv_socket);
+            settings.mpv_socket.clone()
+        } else {
+            tmp_dir.path().join("mpv.pipe")
+        };
+
+        let mut command = std::process::Command::new(&settings.mpv_binary);
+        if let Some(quality) = settings.quality(feed_title) {
+            command.arg(format!("--ytdl-format={}", ytdl_format(quality)));
+        }
+        if let Some(proxy) = settings.proxy(feed_title) {
+            command
+                .arg(format!("--http-proxy={}", proxy))
+                .arg(format!("--ytdl-raw-options-append=proxy={}", proxy));
+        }
+        let spawned = command
+            .args(urls)
+            .arg(format!(
+                "--input-ipc-server={}",
+                pipe_path.to_string_lossy()
+            ))
+            .arg(format!("--start=+{}", start_secs))
+            .args(&settings.mpv_args)
+            .args(args)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => return Ok(Err(format!("Failed to start mpv: {}", e))),
+        };
+
+        // Wait for mpv to open the socket. Connecting may fail for a short while after the
+        // socket file has been created, so we retry until the timeout.
+        let wait_start = std::time::Instant::now();
+        let mut ipc = loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Err(format!("mpv exited with {}", status)));
+            }
+            let connection = if pipe_path.exists() {
+                Some(mpvipc::Mpv::connect(pipe_path.as_path().to_str().unwrap()))
+            } else {
+                None
+            };
+            match connection {
+                Some(Ok(ipc)) => break ipc,
+                _ if wait_start.elapsed() < IPC_TIMEOUT => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                Some(Err(e)) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e.into());
+                }
+                None => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Ok(Err("mpv did not open its ipc socket".to_owned()));
+                }
+            }
+        };
+        if let Err(e) = observe_properties(&mut ipc) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e.into());
+        }
+
+        // The checkpoint is returned as usual once mpv has quit.
+        let checkpoint = SignalCheckpoint::new(pipe_path, |mpv| {
+            let position = mpv.get_property::<f64>("playback-time").ok();
+            let _ = mpv.kill();
+            position
+        })?;
+
+        Ok(Ok(Session {
+            child,
+            ipc,
+            checkpoint,
+            _tmp_dir: tmp_dir,
+        }))
     }
-    let mut mpv = mpvipc::Mpv::connect(pipe_path.as_path().to_str().unwrap()).unwrap();
-
-    //TODO get title?
-
-    mpv.observe_property(0, "playback-time").unwrap();
-    mpv.observe_property(1, "duration").unwrap();
-    mpv.observe_property(2, "media-title").unwrap();
-
-    let mut playback_time = None;
-    let mut duration_secs = None;
-    let mut title = None;
-    while let Ok(e) = mpv.event_listen() {
-        if let mpvipc::Event::PropertyChange { property, .. } = e {
-            match property {
-                mpvipc::Property::PlaybackTime(Some(t)) => {
-                    playback_time = Some(t);
+
+    /// Play all `items` in a single mpv instance. Returns whether playback was interrupted by
+    /// SIGTERM/SIGINT or an error message if mpv failed.
+    pub fn play_playlist(
+        &self,
+        items: &[Active],
+        args: &[String],
+        on_event: &mut dyn FnMut(PlaylistEvent) -> Result<(), Error>,
+    ) -> Result<Result<bool, String>, Error> {
+        let urls = items
+            .iter()
+            .map(|i| self.settings.playback_url(&i.url))
+            .collect::<Vec<_>>();
+        let urls = urls.iter().map(|u| u.as_str()).collect::<Vec<_>>();
+        let feed_title = items.first().and_then(|i| i.feed_title.as_deref());
+        // Playlists get an mpv of their own, whose playlist other instances must not change.
+        let mut session = match self.start(&urls, feed_title, 0.0, args, false)? {
+            Ok(session) => session,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        // The item that is currently playing, identified via mpv's path property.
+        let mut current: Option<(&Active, Playback)> = None;
+        // The first error of `on_event`, after which mpv is stopped.
+        let mut failure = None;
+        while let Ok(e) = session.ipc.event_listen() {
+            if let mpvipc::Event::PropertyChange { property, .. } = e {
+                match property {
+                    mpvipc::Property::Path(Some(path)) => {
+                        if let Some((item, playback)) = current.take() {
+                            if let Err(e) = on_event(PlaylistEvent::Ended(item, playback)) {
+                                failure = Some(e);
+                                break;
+                            }
+                        }
+                        if let Some(item) = items
+                            .iter()
+                            .zip(&urls)
+                            .find(|(_, url)| **url == path)
+                            .map(|(item, _)| item)
+                        {
+                            current = Some((item, Playback::default()));
+                            if let Err(e) = on_event(PlaylistEvent::Started(item)) {
+                                failure = Some(e);
+                                break;
+                            }
+                        }
+                    }
+                    property => {
+                        if let Some((item, playback)) = &mut current {
+                            if let Some(t) = update_playback(playback, property) {
+                                if let Err(e) = on_event(PlaylistEvent::Progress(item, t)) {
+                                    failure = Some(e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
                 }
-                mpvipc::Property::Duration(Some(d)) => {
-                    duration_secs = Some(d);
+            }
+        }
+        if failure.is_some() {
+            let _ = session.ipc.kill();
+        }
+        let (checkpoint, error) = session.finish()?;
+        if let Some(failure) = failure {
+            return Err(failure);
+        }
+        if let Some((item, mut playback)) = current.take() {
+            if let Some(checkpoint) = checkpoint {
+                playback.position_secs = checkpoint.or(playback.position_secs);
+            }
+            on_event(PlaylistEvent::Ended(item, playback))?;
+        }
+        Ok(match error {
+            Some(error) => Err(error),
+            None => Ok(checkpoint.is_some()),
+        })
+    }
+
+    /// Append `url` to the playlist of an mpv that has been started with `reuse_mpv` and follow
+    /// its playback until it has ended. On SIGTERM/SIGINT, the position is saved as for an mpv of
+    /// our own, but mpv keeps running for the other instances.
+    fn enqueue(
+        &self,
+        mut ipc: mpvipc::Mpv,
+        url: &str,
+        start_secs: f64,
+        progress: &mut dyn FnMut(f64) -> Result<(), Error>,
+    ) -> Result<Playback, Error> {
+        observe_properties(&mut ipc)?;
+        ipc.playlist_add(
+            url,
+            mpvipc::PlaylistAddTypeOptions::File,
+            mpvipc::PlaylistAddOptions::AppendPlay,
+        )?;
+
+        // Sent by the signal handler to all clients of mpv, so that this instance stops following
+        // the video.
+        let stop_message = format!("uvp-stop-{}", std::process::id());
+        let checkpoint = {
+            let url = url.to_owned();
+            let stop_message = stop_message.clone();
+            SignalCheckpoint::new(self.settings.mpv_socket.clone(), move |mpv| {
+                let position = match mpv.get_property_string("path") {
+                    Ok(path) if path == url => mpv.get_property::<f64>("playback-time").ok(),
+                    _ => None,
+                };
+                let _ = mpv.run_command_raw("script-message", &[&stop_message]);
+                position
+            })?
+        };
+
+        let mut playback = Playback::default();
+        let mut started = false;
+        let mut playing = false;
+        // The first error of `progress`, after which we stop following the video. mpv keeps
+        // playing it, since other instances may be using mpv.
+        let mut failure = None;
+        while let Ok(e) = ipc.event_listen() {
+            match e {
+                mpvipc::Event::PropertyChange {
+                    property: mpvipc::Property::Path(path),
+                    ..
+                } => {
+                    let was_playing = playing;
+                    playing = path.as_deref() == Some(url);
+                    if was_playing && !playing {
+                        break;
+                    }
+                }
+                // The start position can only be set once the file has been loaded, since the
+                // option would affect the video that is playing when it is appended.
+                mpvipc::Event::FileLoaded if playing && !started => {
+                    started = true;
+                    if start_secs > 0.0 {
+                        let _ = ipc.seek(start_secs, mpvipc::SeekOptions::Absolute);
+                    }
+                }
+                mpvipc::Event::PropertyChange { property, .. } if started => {
+                    if let Some(t) = update_playback(&mut playback, property) {
+                        if let Err(e) = progress(t) {
+                            failure = Some(e);
+                            break;
+                        }
+                    }
                 }
-                mpvipc::Property::Unknown {
-                    name,
-                    data: mpvipc::MpvDataType::String(t),
-                } if name == "media-title" => {
-                    title = Some(t);
+                mpvipc::Event::ClientMessage { args } if args.first() == Some(&stop_message) => {
+                    break;
                 }
                 _ => {}
             }
         }
-    }
-    if duration_secs.is_some()
-        && playback_time.is_some()
-        && playback_time.unwrap() >= duration_secs.unwrap() - END_DETECTION_TOLERANCE_SECONDS
-    {
-        remove_from_active(conn, &active.url)?;
-    } else {
-        if let Some(t) = playback_time {
-            set_position_secs(conn, &active.url, t)?;
-        }
-        if let Some(d) = duration_secs {
-            set_duration(conn, &active.url, d)?;
+        ipc.disconnect();
+        let checkpoint = checkpoint.finish();
+        if let Some(failure) = failure {
+            return Err(failure);
+        }
+        if let Some(checkpoint) = checkpoint {
+            playback.position_secs = checkpoint.or(playback.position_secs);
+            playback.interrupted = true;
+        } else if !started {
+            playback.error = Some("mpv exited before the video has been played".to_owned());
         }
+        Ok(playback)
     }
-    if let (Some(new_title), None) = (title, active.title) {
-        set_title(conn, &active.url, &new_title)?;
+}
+
+impl Player for Mpv<'_> {
+    fn play(
+        &self,
+        active: &Active,
+        start_secs: f64,
+        args: &[String],
+        progress: &mut dyn FnMut(f64) -> Result<(), Error>,
+    ) -> Result<Playback, Error> {
+        let url = self.settings.playback_url(&active.url);
+        // Additional arguments cannot be applied to a running mpv, so it is only reused (and
+        // shared) without.
+        let shared = self.settings.reuse_mpv && args.is_empty();
+        if shared {
+            if let Ok(ipc) = mpvipc::Mpv::connect(&self.settings.mpv_socket.to_string_lossy()) {
+                return self.enqueue(ipc, &url, start_secs, progress);
+            }
+        }
+        let mut session = match self.start(
+            &[&url],
+            active.feed_title.as_deref(),
+            start_secs,
+            args,
+            shared,
+        )? {
+            Ok(session) => session,
+            Err(error) => {
+                return Ok(Playback {
+                    error: Some(error),
+                    ..Default::default()
+                })
+            }
+        };
+
+        let mut playback = Playback::default();
+        // Videos that other instances append to the playlist of a shared mpv are ignored.
+        // Otherwise all files are tracked, e.g. those of a local directory.
+        let mut playing = true;
+        // The first error of `progress`, after which mpv is stopped.
+        let mut failure = None;
+        while let Ok(e) = session.ipc.event_listen() {
+            match e {
+                mpvipc::Event::PropertyChange {
+                    property: mpvipc::Property::Path(path),
+                    ..
+                } => playing = !shared || path.map_or(true, |path| path == url),
+                mpvipc::Event::PropertyChange { property, .. } if playing => {
+                    if let Some(t) = update_playback(&mut playback, property) {
+                        if let Err(e) = progress(t) {
+                            failure = Some(e);
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if failure.is_some() {
+            let _ = session.ipc.kill();
+        }
+        let (checkpoint, error) = session.finish()?;
+        if let Some(failure) = failure {
+            return Err(failure);
+        }
+        if let Some(checkpoint) = checkpoint {
+            playback.position_secs = checkpoint.or(playback.position_secs);
+            playback.interrupted = true;
+        }
+        playback.error = error;
+        Ok(playback)
     }
-    output.wait().unwrap();
-    Ok(())
 }
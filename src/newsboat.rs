@@ -0,0 +1,97 @@
+//! Import of the subscriptions of [newsboat](https://newsboat.org) from its `urls` file.
+//!
+//! Each line of the file contains the url of a feed followed by its tags, which may be quoted.
+//! A tag starting with `~` sets the title of the feed, other tags except for hidden ones (`!`)
+//! are stored as categories of the feed. Query feeds and other urls that newsboat handles
+//! itself (`query:`, `exec:`, `filter:`) are skipped.
+use crate::data::{add_category, add_to_feed};
+use crate::{ignore_constraint_errors, AddFeed, Error};
+use rusqlite::Connection;
+
+pub struct Subscription {
+    pub url: String,
+    pub title: Option<String>,
+    pub categories: Vec<String>,
+}
+
+/// Split a line into whitespace separated words, keeping quoted words (`"..."`) together.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut word = String::new();
+        match chars.peek() {
+            None => break,
+            Some('"') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                    word.push(*c);
+                    chars.next();
+                }
+            }
+        }
+        words.push(word);
+    }
+    words
+}
+
+pub fn parse_urls(urls: &str) -> Vec<Subscription> {
+    urls.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut words = split_words(line).into_iter();
+            let url = words.next()?;
+            if ["query:", "exec:", "filter:"]
+                .iter()
+                .any(|prefix| url.starts_with(prefix))
+            {
+                return None;
+            }
+            let mut subscription = Subscription {
+                url,
+                title: None,
+                categories: Vec::new(),
+            };
+            for tag in words {
+                if let Some(title) = tag.strip_prefix('~') {
+                    subscription.title = Some(title.to_owned());
+                } else if !tag.starts_with('!') {
+                    subscription.categories.push(tag);
+                }
+            }
+            Some(subscription)
+        })
+        .collect()
+}
+
+/// Add the feeds of a newsboat urls file that do not exist yet. Returns the number of
+/// subscriptions in the file.
+pub fn import(conn: &Connection, path: &std::path::Path) -> Result<usize, Error> {
+    let subscriptions = parse_urls(&std::fs::read_to_string(path)?);
+    for subscription in &subscriptions {
+        let feed = AddFeed::Other {
+            title: subscription.title.clone(),
+            url: subscription.url.clone(),
+            auth: Default::default(),
+        }
+        .into();
+        ignore_constraint_errors(add_to_feed(conn, &feed))?;
+        for category in &subscription.categories {
+            add_category(conn, &subscription.url, category)?;
+        }
+    }
+    Ok(subscriptions.len())
+}
@@ -0,0 +1,434 @@
+use crate::data::{
+    available_for_feed, find_in_active, find_in_available, make_active, new_active, playback_lease,
+    release_playback_lease, remove_from_active, set_chapter, set_duration, set_last_played,
+    set_playback_lease, set_position_secs, set_title, Active, PlaybackLease,
+};
+use crate::mpv::{Mpv, PlaylistEvent};
+use crate::{Error, Settings};
+use rusqlite::Connection;
+
+/// How long a video stays leased to the instance that plays it after the last position update.
+/// Leases are released when playback ends, so this only matters if uvp crashed or the video has
+/// been paused for a long time.
+const LEASE_SECS: i64 = 300;
+/// Leases are renewed with position updates, but not more often than this.
+const LEASE_RENEW_SECS: u64 = 30;
+
+/// When a video counts as finished and is removed from the active videos: either at most some
+/// seconds before its end ("30") or after a percentage of it has been played ("95%").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompletionThreshold {
+    SecondsFromEnd(f64),
+    Percentage(f64),
+}
+
+impl CompletionThreshold {
+    pub fn is_finished(&self, position_secs: f64, duration_secs: f64) -> bool {
+        match *self {
+            CompletionThreshold::SecondsFromEnd(s) => position_secs >= duration_secs - s,
+            CompletionThreshold::Percentage(p) => position_secs >= duration_secs * p / 100.0,
+        }
+    }
+}
+
+impl std::str::FromStr for CompletionThreshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            format!(
+                "Invalid completion threshold '{}' (seconds before the end or a percentage like \"95%\")",
+                s
+            )
+        };
+        if let Some(p) = s.strip_suffix('%') {
+            match p.trim().parse::<f64>() {
+                Ok(p) if (0.0..=100.0).contains(&p) => Ok(CompletionThreshold::Percentage(p)),
+                _ => Err(invalid()),
+            }
+        } else {
+            match s.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 => Ok(CompletionThreshold::SecondsFromEnd(secs)),
+                _ => Err(invalid()),
+            }
+        }
+    }
+}
+
+pub enum Outcome {
+    Stopped,
+    /// uvp received SIGTERM/SIGINT during playback. The position has been saved, but the caller
+    /// should terminate, too.
+    Interrupted,
+    /// The video could not be played. The entry has been left untouched.
+    Failed(String),
+    /// The video is being played by another instance of uvp (with the same database). It can be
+    /// played anyway with `force`.
+    InUse(PlaybackLease),
+}
+
+/// Identifies this instance in playback leases.
+fn lease_holder() -> String {
+    format!("{} (pid {})", crate::hostname(), std::process::id())
+}
+
+fn renew_lease(conn: &Connection, url: &str, holder: &str) -> Result<(), rusqlite::Error> {
+    let expires = chrono::Local::now() + chrono::Duration::seconds(LEASE_SECS);
+    set_playback_lease(conn, url, holder, &expires.into())
+}
+
+/// What a player found out about the video during playback. Players that cannot be queried
+/// leave the fields empty, in which case the stored state is left as is.
+#[derive(Default)]
+pub struct Playback {
+    pub position_secs: Option<f64>,
+    pub duration_secs: Option<f64>,
+    pub title: Option<String>,
+    /// The current chapter (starting at 0) and the start times of all chapters.
+    pub chapter: Option<i64>,
+    pub chapters: Vec<f64>,
+    pub interrupted: bool,
+    /// Set if the player failed to play the video.
+    pub error: Option<String>,
+}
+
+pub trait Player {
+    /// Play the video starting at `start_secs` and block until playback has ended. `progress`
+    /// should be called with the current position whenever it changes.
+    fn play(
+        &self,
+        active: &Active,
+        start_secs: f64,
+        args: &[String],
+        progress: &mut dyn FnMut(f64) -> Result<(), Error>,
+    ) -> Result<Playback, Error>;
+}
+
+/// A player without position tracking that is started from a command template like
+/// `vlc --start-time={start} {url}`. The url is appended if there is no `{url}` placeholder.
+pub struct CommandPlayer<'a> {
+    template: &'a str,
+    settings: &'a Settings,
+}
+
+impl Player for CommandPlayer<'_> {
+    fn play(
+        &self,
+        active: &Active,
+        start_secs: f64,
+        args: &[String],
+        _progress: &mut dyn FnMut(f64) -> Result<(), Error>,
+    ) -> Result<Playback, Error> {
+        let start = format!("{}", start_secs as u64);
+        let url = self.settings.playback_url(&active.url);
+        let mut words = self.template.split_whitespace().map(|w| {
+            w.replace("{url}", &url)
+                .replace("{start}", &start)
+                .replace("{title}", active.title.as_deref().unwrap_or(""))
+        });
+        let binary = if let Some(binary) = words.next() {
+            binary
+        } else {
+            return Ok(Playback {
+                error: Some("Empty player command".to_owned()),
+                ..Default::default()
+            });
+        };
+        let mut command = std::process::Command::new(binary);
+        command.args(words).args(args);
+        if !self.template.contains("{url}") {
+            command.arg(&url);
+        }
+        let error = match command.status() {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(format!("Player exited with {}", status)),
+            Err(e) => Some(format!("Failed to start player: {}", e)),
+        };
+        Ok(Playback {
+            error,
+            ..Default::default()
+        })
+    }
+}
+
+/// Play `url` with the configured player. `args` are passed to the player in addition to the
+/// configured ones. Unless `force` is set, videos that are being played by another instance are
+/// not played.
+pub fn play(
+    conn: &Connection,
+    url: &str,
+    settings: &Settings,
+    args: &[String],
+    force: bool,
+) -> Result<Outcome, Error> {
+    if !force {
+        if let Some(lease) = playback_lease(conn, url)? {
+            return Ok(Outcome::InUse(lease));
+        }
+    }
+    let known_title = find_in_active(conn, url)?
+        .and_then(|a| a.title)
+        .or(find_in_available(conn, url)?.map(|a| a.title));
+    if settings.hooks.pre_play(url, known_title.as_deref()).veto {
+        return Ok(Outcome::Stopped);
+    }
+
+    // The entry is only made active once playback has actually started, so that it stays where
+    // it is if the video cannot be played.
+    let (active, mut activated) = match find_in_active(conn, url)? {
+        Some(active) => (active, true),
+        None => (new_active(conn, url)?, false),
+    };
+
+    let player: Box<dyn Player> = if let Some(template) = &settings.player_command {
+        Box::new(CommandPlayer { template, settings })
+    } else {
+        Box::new(Mpv::new(settings))
+    };
+
+    let holder = lease_holder();
+    let mut last_renew = std::time::Instant::now();
+    let renew_interval = std::time::Duration::from_secs(LEASE_RENEW_SECS);
+    // Persist the position now and then so that it survives a crash.
+    let mut last_save = std::time::Instant::now();
+    let save_interval = std::time::Duration::from_secs(settings.position_save_interval_secs);
+    let mut progress = |position_secs| -> Result<(), Error> {
+        if last_renew.elapsed() >= renew_interval {
+            renew_lease(conn, url, &holder)?;
+            last_renew = std::time::Instant::now();
+        }
+        if settings.position_save_interval_secs > 0 && last_save.elapsed() >= save_interval {
+            if !activated && position_secs < settings.min_position_to_save_secs {
+                return Ok(());
+            }
+            if !activated {
+                crate::ignore_constraint_errors(make_active(conn, url))?;
+                activated = true;
+            }
+            set_position_secs(conn, &active.url, position_secs)?;
+            last_save = std::time::Instant::now();
+        }
+        Ok(())
+    };
+    // Start a little earlier when resuming to get some context.
+    let start_secs = if let (true, Some(chapter_start_secs)) =
+        (settings.resume_at_chapter, active.chapter_start_secs)
+    {
+        chapter_start_secs
+    } else if active.position_secs > 0.0 {
+        (active.position_secs - settings.resume_rewind_secs).max(0.0)
+    } else {
+        0.0
+    };
+    // The lease also tells other instances (e.g. the tui) what is being played.
+    renew_lease(conn, url, &holder)?;
+    let playback = player.play(&active, start_secs, args, &mut progress);
+    release_playback_lease(conn, url, &holder)?;
+    let playback = playback?;
+    if let Some(error) = &playback.error {
+        return Ok(Outcome::Failed(format!(
+            "Failed to play {}: {}",
+            url, error
+        )));
+    }
+    let outcome = if playback.interrupted {
+        Outcome::Interrupted
+    } else {
+        Outcome::Stopped
+    };
+    if !activated {
+        // The video has only been opened briefly, so it stays available.
+        if is_trivial(settings, &playback) {
+            return Ok(outcome);
+        }
+        crate::ignore_constraint_errors(make_active(conn, url))?;
+    }
+    set_last_played(conn, &active.url, &chrono::Local::now().into())?;
+
+    record_playback(conn, settings, &active, &playback)?;
+    Ok(outcome)
+}
+
+fn is_finished(settings: &Settings, playback: &Playback) -> bool {
+    match (playback.position_secs, playback.duration_secs) {
+        (Some(t), Some(d)) => settings.completion_threshold.is_finished(t, d),
+        _ => false,
+    }
+}
+
+/// Whether the video has been stopped too early (see `min_position_to_save_secs`) for the
+/// playback to be recorded.
+fn is_trivial(settings: &Settings, playback: &Playback) -> bool {
+    playback
+        .position_secs
+        .map_or(false, |t| t < settings.min_position_to_save_secs)
+        && !is_finished(settings, playback)
+}
+
+/// Store what the player found out about `active` and run the post_play hook. Finished videos
+/// are removed from the list of active videos.
+fn record_playback(
+    conn: &Connection,
+    settings: &Settings,
+    active: &Active,
+    playback: &Playback,
+) -> Result<(), Error> {
+    let finished = is_finished(settings, playback);
+    if finished {
+        remove_from_active(conn, &active.url)?;
+        settings.hooks.on_playback_finished(
+            &active.url,
+            active.title.as_deref().or(playback.title.as_deref()),
+            playback.duration_secs.or(active.duration_secs),
+        );
+    } else {
+        if let Some(t) = playback.position_secs {
+            set_position_secs(conn, &active.url, t)?;
+        }
+        if let Some(d) = playback.duration_secs {
+            set_duration(conn, &active.url, d)?;
+        }
+        if let Some(chapter) = playback.chapter {
+            if let Some(chapter_start_secs) = playback.chapters.get(chapter as usize) {
+                let count = playback.chapters.len() as i64;
+                set_chapter(conn, &active.url, chapter, count, *chapter_start_secs)?;
+            }
+        }
+    }
+    if let (Some(new_title), None) = (&playback.title, &active.title) {
+        set_title(conn, &active.url, new_title)?;
+    }
+    settings.hooks.post_play(
+        &active.url,
+        active.title.as_deref().or(playback.title.as_deref()),
+        playback.position_secs,
+        playback.duration_secs,
+        finished,
+    );
+    Ok(())
+}
+
+/// Play all available videos of a feed (oldest first) as a playlist in a single mpv instance.
+/// Each video is made active once it has been played for `min_position_to_save_secs` and its
+/// position is tracked separately. Videos that are being played by another instance are left out.
+pub fn play_feed(
+    conn: &Connection,
+    feed_url_or_title: &str,
+    settings: &Settings,
+    args: &[String],
+) -> Result<Outcome, Error> {
+    if settings.player_command.is_some() {
+        return Ok(Outcome::Failed(
+            "Playlists are only supported with mpv".to_owned(),
+        ));
+    }
+    let mut available = available_for_feed(conn, feed_url_or_title)?
+        .into_iter()
+        .filter_map(|a| match playback_lease(conn, &a.url) {
+            Ok(Some(_)) => None,
+            Ok(None) => Some(Ok(a)),
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if available.is_empty() {
+        return Ok(Outcome::Failed(format!(
+            "No available videos in feed {}",
+            feed_url_or_title
+        )));
+    }
+    available.reverse();
+    let items = available
+        .iter()
+        .map(|a| new_active(conn, &a.url))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let holder = lease_holder();
+    let mut last_renew = std::time::Instant::now();
+    let renew_interval = std::time::Duration::from_secs(LEASE_RENEW_SECS);
+    let mut last_save = std::time::Instant::now();
+    let save_interval = std::time::Duration::from_secs(settings.position_save_interval_secs);
+    let mut activated = std::collections::HashSet::new();
+    let mut activate = |url: &str| -> Result<(), Error> {
+        if activated.insert(url.to_owned()) {
+            crate::ignore_constraint_errors(make_active(conn, url))?;
+            set_last_played(conn, url, &chrono::Local::now().into())?;
+        }
+        Ok(())
+    };
+    let mut on_event = |event: PlaylistEvent| -> Result<(), Error> {
+        match event {
+            PlaylistEvent::Started(active) => {
+                renew_lease(conn, &active.url, &holder)?;
+                last_renew = std::time::Instant::now();
+            }
+            PlaylistEvent::Progress(active, position_secs) => {
+                if last_renew.elapsed() >= renew_interval {
+                    renew_lease(conn, &active.url, &holder)?;
+                    last_renew = std::time::Instant::now();
+                }
+                if position_secs >= settings.min_position_to_save_secs {
+                    activate(&active.url)?;
+                    if settings.position_save_interval_secs > 0
+                        && last_save.elapsed() >= save_interval
+                    {
+                        set_position_secs(conn, &active.url, position_secs)?;
+                        last_save = std::time::Instant::now();
+                    }
+                }
+            }
+            PlaylistEvent::Ended(active, playback) => {
+                release_playback_lease(conn, &active.url, &holder)?;
+                // Videos that have only been skipped through stay available.
+                if !is_trivial(settings, &playback) {
+                    activate(&active.url)?;
+                    record_playback(conn, settings, active, &playback)?;
+                }
+            }
+        }
+        Ok(())
+    };
+    let result = Mpv::new(settings).play_playlist(&items, args, &mut on_event);
+    // The end of the video that was playing is not handled after an error.
+    if result.is_err() {
+        for item in &items {
+            release_playback_lease(conn, &item.url, &holder)?;
+        }
+    }
+    Ok(match result? {
+        Ok(true) => Outcome::Interrupted,
+        Ok(false) => Outcome::Stopped,
+        Err(error) => Outcome::Failed(format!(
+            "Failed to play feed {}: {}",
+            feed_url_or_title, error
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_completion_thresholds() {
+        assert!(matches!(
+            " 95 % ".parse(),
+            Ok(CompletionThreshold::Percentage(p)) if p == 95.0
+        ));
+        assert!(matches!(
+            "100%".parse(),
+            Ok(CompletionThreshold::Percentage(p)) if p == 100.0
+        ));
+        assert!(matches!(
+            "30".parse(),
+            Ok(CompletionThreshold::SecondsFromEnd(s)) if s == 30.0
+        ));
+        for threshold in &["", "%", "101%", "-1%", "-5", "95%%", "abc"] {
+            assert!(
+                threshold.parse::<CompletionThreshold>().is_err(),
+                "{}",
+                threshold
+            );
+        }
+    }
+}
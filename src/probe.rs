@@ -0,0 +1,132 @@
+//! Durations of available videos whose feeds do not include them (e.g. youtube feeds), probed
+//! with yt-dlp or, for local files, ffprobe if `probe_durations` is set.
+//!
+//! Probes are run one at a time and not more often than every `PROBE_INTERVAL` by a `Prober`,
+//! which the tui polls in the background. `uvp refresh` probes the newest videos at its end.
+use crate::data::{available_without_duration, find_in_available, set_available_duration};
+use crate::{Error, Settings};
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Minimum time between the starts of two probes, so that sites are not flooded with requests.
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+/// Maximum number of videos that are probed by `run`.
+const MAX_PROBES_PER_RUN: usize = 50;
+
+fn start(conn: &Connection, settings: &Settings, url: &str) -> Result<Child, Error> {
+    let mut command = if url.starts_with('/') {
+        let mut command = Command::new("ffprobe");
+        command.args(&[
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ]);
+        command
+    } else {
+        let feed_title = find_in_available(conn, url)?.map(|available| available.feed.title);
+        let mut command = Command::new(&settings.download_binary);
+        if let Some(proxy) = settings.proxy(feed_title.as_deref()) {
+            command.arg("--proxy").arg(proxy);
+        }
+        command.args(&["--print", "duration", "--skip-download", "--no-warnings"]);
+        command
+    };
+    Ok(command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?)
+}
+
+pub struct Prober {
+    running: Option<(String, Child)>,
+    last_start: Option<Instant>,
+    /// Videos that have been probed (successfully or not) are not probed again.
+    probed: HashSet<String>,
+    limit: Option<usize>,
+    /// No video without a duration was left at the last poll.
+    exhausted: bool,
+}
+
+impl Prober {
+    /// A prober that probes at most `limit` videos.
+    pub fn new(limit: Option<usize>) -> Self {
+        Prober {
+            running: None,
+            last_start: None,
+            probed: HashSet::new(),
+            limit,
+            exhausted: false,
+        }
+    }
+
+    /// Store the duration of a finished probe and start the next one if it is time. Returns
+    /// whether a duration has been stored.
+    pub fn poll(&mut self, conn: &Connection, settings: &Settings) -> Result<bool, Error> {
+        let mut stored = false;
+        if let Some((_, child)) = &mut self.running {
+            if child.try_wait()?.is_none() {
+                return Ok(false);
+            }
+            let (url, child) = self.running.take().unwrap();
+            let output = child.wait_with_output()?;
+            let duration = String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .parse::<f64>()
+                .ok();
+            if let (true, Some(duration)) = (output.status.success(), duration) {
+                set_available_duration(conn, &url, duration)?;
+                stored = true;
+            }
+        }
+        if self.limit.map_or(false, |limit| self.probed.len() >= limit)
+            || self
+                .last_start
+                .map_or(false, |t| t.elapsed() < PROBE_INTERVAL)
+        {
+            return Ok(stored);
+        }
+        // Videos that could not be probed remain without a duration, so at most all of them
+        // precede the next one to probe.
+        let next = available_without_duration(conn, self.probed.len() + 1)?
+            .into_iter()
+            .find(|url| !self.probed.contains(url));
+        self.exhausted = next.is_none();
+        if let Some(url) = next {
+            self.probed.insert(url.clone());
+            self.last_start = Some(Instant::now());
+            self.running = Some((url.clone(), start(conn, settings, &url)?));
+        }
+        Ok(stored)
+    }
+
+    /// Whether no probe is running and no further one will be started.
+    pub fn is_done(&self) -> bool {
+        self.running.is_none()
+            && (self.exhausted || self.limit.map_or(false, |limit| self.probed.len() >= limit))
+    }
+}
+
+/// Probe the durations of the newest videos without one. Returns the number of durations that
+/// have been found.
+pub fn run(conn: &Connection, settings: &Settings) -> Result<usize, Error> {
+    let mut prober = Prober::new(Some(MAX_PROBES_PER_RUN));
+    let mut stored = 0;
+    loop {
+        if prober.poll(conn, settings)? {
+            stored += 1;
+        }
+        if prober.is_done() {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Ok(stored)
+}
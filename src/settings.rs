@@ -0,0 +1,748 @@
+//! Settings of uvp and how they are loaded from the config files.
+use crate::data::ActiveOrder;
+use crate::hooks::Hooks;
+use crate::player::CompletionThreshold;
+use crate::{feeds, tui, Error};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::path::PathBuf;
+use unsegen::base::Color;
+
+pub const DB_NAME: &'static str = "uvp.db";
+pub const CONFIG_FILE_NAME: &'static str = "uvp.toml";
+
+pub const DB_FILE_CONFIG_KEY: &'static str = "database_file";
+pub const PLAYER_COMMAND_CONFIG_KEY: &'static str = "player_command";
+pub const MPV_BINARY_CONFIG_KEY: &'static str = "mpv_binary";
+pub const MPV_ARGS_CONFIG_KEY: &'static str = "mpv_args";
+pub const REUSE_MPV_CONFIG_KEY: &'static str = "reuse_mpv";
+pub const MPV_SOCKET_CONFIG_KEY: &'static str = "mpv_socket";
+pub const QUALITY_CONFIG_KEY: &'static str = "quality";
+pub const FEEDS_CONFIG_KEY: &'static str = "feeds";
+pub const RESUME_REWIND_CONFIG_KEY: &'static str = "resume_rewind";
+pub const RESUME_AT_CHAPTER_CONFIG_KEY: &'static str = "resume_at_chapter";
+pub const POSITION_SAVE_INTERVAL_CONFIG_KEY: &'static str = "position_save_interval";
+pub const BROWSER_BINARY_CONFIG_KEY: &'static str = "browser_binary";
+pub const CLIPBOARD_COMMAND_CONFIG_KEY: &'static str = "clipboard_command";
+pub const PICKER_COMMAND_CONFIG_KEY: &'static str = "picker_command";
+pub const THEME_CONFIG_KEY: &'static str = "theme";
+pub const ACTIVE_ORDER_CONFIG_KEY: &'static str = "active_order";
+pub const DATE_FORMAT_CONFIG_KEY: &'static str = "date_format";
+pub const LAYOUT_CONFIG_KEY: &'static str = "layout";
+pub const LAYOUT_RATIO_CONFIG_KEY: &'static str = "layout_ratio";
+pub const PROBE_DURATIONS_CONFIG_KEY: &'static str = "probe_durations";
+pub const DOWNLOAD_BINARY_CONFIG_KEY: &'static str = "download_binary";
+pub const DOWNLOAD_DIR_CONFIG_KEY: &'static str = "download_dir";
+pub const DOWNLOAD_CONCURRENCY_CONFIG_KEY: &'static str = "download_concurrency";
+pub const DOWNLOAD_MAX_BYTES_CONFIG_KEY: &'static str = "download_max_bytes";
+pub const DOWNLOAD_KEEP_DAYS_CONFIG_KEY: &'static str = "download_keep_days";
+pub const BLOCKLIST_CONFIG_KEY: &'static str = "blocklist";
+pub const SKIP_SHORTS_CONFIG_KEY: &'static str = "skip_shorts";
+pub const SHORTS_MAX_DURATION_CONFIG_KEY: &'static str = "shorts_max_duration";
+pub const COMPLETION_THRESHOLD_CONFIG_KEY: &'static str = "completion_threshold";
+pub const MIN_POSITION_TO_SAVE_CONFIG_KEY: &'static str = "min_position_to_save_secs";
+pub const PROXY_CONFIG_KEY: &'static str = "proxy";
+pub const FETCH_RETRIES_CONFIG_KEY: &'static str = "fetch_retries";
+pub const FETCH_RETRY_DELAY_CONFIG_KEY: &'static str = "fetch_retry_delay";
+pub const REFRESH_FRESHNESS_CONFIG_KEY: &'static str = "refresh_freshness";
+pub const ALIASES_CONFIG_KEY: &'static str = "aliases";
+pub const DEFAULT_COMMAND_CONFIG_KEY: &'static str = "default_command";
+pub const YOUTUBE_FEED_URL_CONFIG_KEY: &'static str = "youtube_feed_url";
+pub const YOUTUBE_WATCH_URL_CONFIG_KEY: &'static str = "youtube_watch_url";
+
+/// Settings that can be overridden for individual feeds in a `[feeds."<title or url>"]` table.
+#[derive(Default)]
+pub struct FeedSettings {
+    pub quality: Option<String>,
+    /// Queue new entries for download during a refresh.
+    pub auto_download: bool,
+    /// Add new entries to the front of the active videos instead of the available ones.
+    pub auto_activate: bool,
+    pub skip_shorts: Option<bool>,
+    /// New entries that are known to be shorter or longer (in seconds) are not added.
+    pub min_duration_secs: Option<f64>,
+    pub max_duration_secs: Option<f64>,
+    pub proxy: Option<String>,
+    /// Color of the feed's title in the tui instead of the one derived from the title.
+    pub color: Option<Color>,
+}
+
+impl TryFrom<config::Value> for FeedSettings {
+    type Error = Error;
+
+    fn try_from(value: config::Value) -> Result<Self, Self::Error> {
+        let value = value.into_table()?;
+        let get_string = |key: &str| value.get(key).cloned().map(|v| v.into_string()).transpose();
+        let get_float = |key: &str| value.get(key).cloned().map(|v| v.into_float()).transpose();
+        Ok(FeedSettings {
+            quality: get_string("quality")?,
+            auto_download: value
+                .get("auto_download")
+                .cloned()
+                .map(|v| v.into_bool())
+                .transpose()?
+                .unwrap_or(false),
+            auto_activate: value
+                .get("auto_activate")
+                .cloned()
+                .map(|v| v.into_bool())
+                .transpose()?
+                .unwrap_or(false),
+            skip_shorts: value
+                .get("skip_shorts")
+                .cloned()
+                .map(|v| v.into_bool())
+                .transpose()?,
+            min_duration_secs: get_float("min_duration")?,
+            max_duration_secs: get_float("max_duration")?,
+            proxy: get_string("proxy")?,
+            color: get_string("color")?.map(|c| parse_color(&c)).transpose()?,
+        })
+    }
+}
+
+pub struct Settings {
+    pub database_file: PathBuf,
+    /// Command template of an alternative player (see `player::CommandPlayer`), mpv otherwise.
+    pub player_command: Option<String>,
+    pub mpv_binary: String,
+    pub mpv_args: Vec<String>,
+    /// Append videos to the playlist of a running mpv (listening on `mpv_socket`) instead of
+    /// starting a new one.
+    pub reuse_mpv: bool,
+    pub mpv_socket: PathBuf,
+    /// Maximum video quality, e.g. "720p", or a ytdl format string.
+    pub quality: Option<String>,
+    /// Seconds to go back from the stored position when resuming playback.
+    pub resume_rewind_secs: f64,
+    /// Resume at the start of the chapter instead of the exact position.
+    pub resume_at_chapter: bool,
+    /// When a played video is removed from the active videos.
+    pub completion_threshold: CompletionThreshold,
+    /// Available videos that are stopped before this position stay available instead of
+    /// becoming active.
+    pub min_position_to_save_secs: f64,
+    /// Seconds between saves of the playback position during playback (0: only at the end).
+    pub position_save_interval_secs: u64,
+    /// Templates of the urls of an alternative youtube front-end (e.g., Invidious or Piped) that
+    /// are used to fetch channel feeds (`{channel_id}`) and to play videos (`{id}`).
+    pub youtube_feed_url: Option<String>,
+    pub youtube_watch_url: Option<String>,
+    /// Proxy (e.g. "http://proxy:8080" or "socks5://127.0.0.1:9050") for fetching feeds, playback
+    /// and downloads.
+    pub proxy: Option<String>,
+    /// How often fetching a feed is retried after a transient error (e.g., a timeout).
+    pub fetch_retries: usize,
+    /// Seconds before the first retry, doubled for each further one.
+    pub fetch_retry_delay_secs: f64,
+    /// Feeds fetched within this many seconds are skipped when refreshing all feeds (0: never).
+    pub refresh_freshness_secs: u64,
+    pub browser_binary: String,
+    pub clipboard_command: String,
+    /// Shell command that receives lines on stdin and prints the selected one (`uvp pick`).
+    pub picker_command: String,
+    /// Fill in missing durations of available videos (see `probe`).
+    pub probe_durations: bool,
+    /// yt-dlp or a program with compatible arguments.
+    pub download_binary: String,
+    pub download_dir: PathBuf,
+    /// Maximum number of simultaneous downloads.
+    pub download_concurrency: usize,
+    /// Limits for downloaded files, see `download::prune`.
+    pub download_max_bytes: Option<u64>,
+    pub download_keep_days: Option<u64>,
+    /// Do not add youtube shorts (short links or videos up to `shorts_max_duration_secs`) during
+    /// a refresh.
+    pub skip_shorts: bool,
+    pub shorts_max_duration_secs: f64,
+    pub theme: Theme,
+    pub date_format: tui::DateFormat,
+    pub layout: tui::Split,
+    /// Share of the active list of the space of both lists (0 to 1).
+    pub layout_ratio: f64,
+    pub active_order: ActiveOrder,
+    pub hooks: Hooks,
+    pub feeds: HashMap<String, FeedSettings>,
+    /// New entries whose titles match one of these (case insensitive) regexes are not added.
+    pub blocklist: Vec<regex::Regex>,
+    /// Names of custom subcommands and the command lines they stand for.
+    pub aliases: HashMap<String, String>,
+    /// Command line used when uvp is run without a subcommand.
+    pub default_command: Option<String>,
+}
+
+/// A config builder with the default values of all settings.
+pub fn config_builder() -> Result<config::ConfigBuilder<config::builder::DefaultState>, Error> {
+    Ok(config::Config::builder()
+        .set_default(
+            DB_FILE_CONFIG_KEY,
+            default_database_file().to_string_lossy().as_ref(),
+        )?
+        .set_default(MPV_BINARY_CONFIG_KEY, "mpv")?
+        .set_default(MPV_ARGS_CONFIG_KEY, vec!["--force-window=immediate"])?
+        .set_default(REUSE_MPV_CONFIG_KEY, false)?
+        .set_default(
+            MPV_SOCKET_CONFIG_KEY,
+            default_mpv_socket().to_string_lossy().as_ref(),
+        )?
+        .set_default(RESUME_REWIND_CONFIG_KEY, 5.0)?
+        .set_default(RESUME_AT_CHAPTER_CONFIG_KEY, false)?
+        .set_default(COMPLETION_THRESHOLD_CONFIG_KEY, "1")?
+        .set_default(MIN_POSITION_TO_SAVE_CONFIG_KEY, 30.0)?
+        .set_default(POSITION_SAVE_INTERVAL_CONFIG_KEY, 10)?
+        .set_default(FETCH_RETRIES_CONFIG_KEY, 2)?
+        .set_default(FETCH_RETRY_DELAY_CONFIG_KEY, 1.0)?
+        .set_default(REFRESH_FRESHNESS_CONFIG_KEY, 0)?
+        .set_default(BROWSER_BINARY_CONFIG_KEY, "xdg-open")?
+        .set_default(CLIPBOARD_COMMAND_CONFIG_KEY, "xclip -selection clipboard")?
+        .set_default(PICKER_COMMAND_CONFIG_KEY, "fzf")?
+        .set_default(PROBE_DURATIONS_CONFIG_KEY, false)?
+        .set_default(DOWNLOAD_BINARY_CONFIG_KEY, "yt-dlp")?
+        .set_default(
+            DOWNLOAD_DIR_CONFIG_KEY,
+            default_download_dir().to_string_lossy().as_ref(),
+        )?
+        .set_default(DOWNLOAD_CONCURRENCY_CONFIG_KEY, 2)?
+        .set_default(SKIP_SHORTS_CONFIG_KEY, false)?
+        .set_default(BLOCKLIST_CONFIG_KEY, Vec::<String>::new())?
+        .set_default(SHORTS_MAX_DURATION_CONFIG_KEY, 60.0)?
+        .set_default(ACTIVE_ORDER_CONFIG_KEY, "insertion")?
+        .set_default(DATE_FORMAT_CONFIG_KEY, "relative")?
+        .set_default(LAYOUT_CONFIG_KEY, "horizontal")?
+        .set_default(LAYOUT_RATIO_CONFIG_KEY, 0.5)?)
+}
+
+/// The existing config files in `/etc`, `/usr/etc` and the user's config directory. Later files
+/// take precedence.
+pub fn config_files() -> Vec<PathBuf> {
+    vec![
+        Some(PathBuf::from("/etc")),
+        Some(PathBuf::from("/usr/etc")),
+        dirs::config_dir(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|location| location.join(CONFIG_FILE_NAME))
+    .filter(|file| file.is_file())
+    .collect()
+}
+
+/// The table at `key`, empty if the key is missing. Other errors, e.g. a value that is not a
+/// table, are returned.
+fn get_table_or_default(
+    config: &config::Config,
+    key: &str,
+) -> Result<config::Map<String, config::Value>, config::ConfigError> {
+    match config.get_table(key) {
+        Err(config::ConfigError::NotFound(_)) => Ok(Default::default()),
+        table => table,
+    }
+}
+
+/// `None` if the key is missing. Other errors, e.g. a value of the wrong type, are returned.
+fn optional<T>(value: Result<T, config::ConfigError>) -> Result<Option<T>, config::ConfigError> {
+    match value {
+        Err(config::ConfigError::NotFound(_)) => Ok(None),
+        value => value.map(Some),
+    }
+}
+
+/// Load the settings from the config files.
+pub fn load() -> Result<Settings, Error> {
+    let mut builder = config_builder()?;
+    for config_file in config_files() {
+        builder = builder.add_source(config::File::new(
+            config_file.to_str().unwrap(),
+            config::FileFormat::Toml,
+        ));
+    }
+    Settings::from_config(&builder.build()?)
+}
+
+impl Settings {
+    /// Read the settings from a config that contains the defaults of `config_builder`.
+    pub fn from_config(config: &config::Config) -> Result<Self, Error> {
+        Ok(Settings {
+            database_file: config.get_string(DB_FILE_CONFIG_KEY)?.into(),
+            player_command: config.get_string(PLAYER_COMMAND_CONFIG_KEY).ok(),
+            mpv_binary: config.get_string(MPV_BINARY_CONFIG_KEY)?,
+            mpv_args: config.get::<Vec<String>>(MPV_ARGS_CONFIG_KEY)?,
+            reuse_mpv: config.get_bool(REUSE_MPV_CONFIG_KEY)?,
+            mpv_socket: config.get_string(MPV_SOCKET_CONFIG_KEY)?.into(),
+            quality: config.get_string(QUALITY_CONFIG_KEY).ok(),
+            resume_rewind_secs: config.get_float(RESUME_REWIND_CONFIG_KEY)?,
+            resume_at_chapter: config.get_bool(RESUME_AT_CHAPTER_CONFIG_KEY)?,
+            completion_threshold: config
+                .get_string(COMPLETION_THRESHOLD_CONFIG_KEY)?
+                .parse()
+                .map_err(config::ConfigError::Message)?,
+            min_position_to_save_secs: config.get_float(MIN_POSITION_TO_SAVE_CONFIG_KEY)?,
+            position_save_interval_secs: config.get::<u64>(POSITION_SAVE_INTERVAL_CONFIG_KEY)?,
+            proxy: config.get_string(PROXY_CONFIG_KEY).ok(),
+            fetch_retries: config.get::<usize>(FETCH_RETRIES_CONFIG_KEY)?,
+            fetch_retry_delay_secs: config.get_float(FETCH_RETRY_DELAY_CONFIG_KEY)?,
+            refresh_freshness_secs: config.get::<u64>(REFRESH_FRESHNESS_CONFIG_KEY)?,
+            youtube_feed_url: config.get_string(YOUTUBE_FEED_URL_CONFIG_KEY).ok(),
+            youtube_watch_url: config.get_string(YOUTUBE_WATCH_URL_CONFIG_KEY).ok(),
+            browser_binary: config.get_string(BROWSER_BINARY_CONFIG_KEY)?,
+            clipboard_command: config.get_string(CLIPBOARD_COMMAND_CONFIG_KEY)?,
+            picker_command: config.get_string(PICKER_COMMAND_CONFIG_KEY)?,
+            probe_durations: config.get_bool(PROBE_DURATIONS_CONFIG_KEY)?,
+            download_binary: config.get_string(DOWNLOAD_BINARY_CONFIG_KEY)?,
+            download_dir: config.get_string(DOWNLOAD_DIR_CONFIG_KEY)?.into(),
+            download_concurrency: config.get::<usize>(DOWNLOAD_CONCURRENCY_CONFIG_KEY)?,
+            download_max_bytes: config.get::<u64>(DOWNLOAD_MAX_BYTES_CONFIG_KEY).ok(),
+            download_keep_days: config.get::<u64>(DOWNLOAD_KEEP_DAYS_CONFIG_KEY).ok(),
+            skip_shorts: config.get_bool(SKIP_SHORTS_CONFIG_KEY)?,
+            blocklist: config
+                .get::<Vec<String>>(BLOCKLIST_CONFIG_KEY)?
+                .iter()
+                .map(|pattern| {
+                    regex::RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .map_err(|e| {
+                            config::ConfigError::Message(format!(
+                                "Invalid blocklist entry '{}': {}",
+                                pattern, e
+                            ))
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            shorts_max_duration_secs: config.get_float(SHORTS_MAX_DURATION_CONFIG_KEY)?,
+            theme: get_table_or_default(config, THEME_CONFIG_KEY)?.try_into()?,
+            date_format: config
+                .get_string(DATE_FORMAT_CONFIG_KEY)?
+                .parse()
+                .map_err(config::ConfigError::Message)?,
+            layout: config
+                .get_string(LAYOUT_CONFIG_KEY)?
+                .parse()
+                .map_err(config::ConfigError::Message)?,
+            layout_ratio: config.get_float(LAYOUT_RATIO_CONFIG_KEY)?.max(0.1).min(0.9),
+            active_order: config
+                .get_string(ACTIVE_ORDER_CONFIG_KEY)?
+                .parse()
+                .map_err(config::ConfigError::Message)?,
+            hooks: Hooks::from_config(config),
+            feeds: get_table_or_default(config, FEEDS_CONFIG_KEY)?
+                .into_iter()
+                .map(|(feed, settings)| Ok((feed, settings.try_into()?)))
+                .collect::<Result<_, Error>>()?,
+            aliases: get_table_or_default(config, ALIASES_CONFIG_KEY)?
+                .into_iter()
+                .map(|(alias, command)| Ok((alias, command.into_string()?)))
+                .collect::<Result<_, Error>>()?,
+            default_command: optional(config.get_string(DEFAULT_COMMAND_CONFIG_KEY))?,
+        })
+    }
+
+    /// Settings of the feed with the given title or url, if any have been configured.
+    pub fn feed(&self, title_or_url: &str) -> Option<&FeedSettings> {
+        self.feeds.get(title_or_url)
+    }
+
+    /// The color of the feed's title in the tui: the configured one or one picked from the
+    /// palette by the title, so that it is the same in every session.
+    pub fn feed_color(&self, feed_title: &str) -> Option<Color> {
+        if let Some(color) = self.feed(feed_title).and_then(|f| f.color) {
+            return Some(color);
+        }
+        if !self.theme.feed_colors || feed_title.is_empty() {
+            return None;
+        }
+        // FNV-1a, which unlike the hasher of std is guaranteed to stay the same.
+        let hash = feed_title.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        Some(FEED_PALETTE[(hash % FEED_PALETTE.len() as u64) as usize])
+    }
+
+    pub fn quality(&self, feed_title: Option<&str>) -> Option<&str> {
+        feed_title
+            .and_then(|t| self.feed(t))
+            .and_then(|f| f.quality.as_deref())
+            .or(self.quality.as_deref())
+    }
+
+    pub fn proxy(&self, feed_title: Option<&str>) -> Option<&str> {
+        feed_title
+            .and_then(|t| self.feed(t))
+            .and_then(|f| f.proxy.as_deref())
+            .or(self.proxy.as_deref())
+    }
+
+    /// The proxy for fetching the feed, which may be configured via its title or url.
+    pub fn feed_proxy(&self, feed: &crate::data::Feed) -> Option<&str> {
+        self.feed(&feed.title)
+            .or_else(|| self.feed(&feed.url))
+            .and_then(|f| f.proxy.as_deref())
+            .or(self.proxy.as_deref())
+    }
+
+    /// The url that is fetched for the feed with the url, see `youtube_feed_url`.
+    pub fn fetch_url(&self, url: &str) -> String {
+        match (&self.youtube_feed_url, feeds::youtube_channel_id(url)) {
+            (Some(template), Some(id)) => template.replace("{channel_id}", id),
+            _ => url.to_owned(),
+        }
+    }
+
+    /// The url that is passed to the player for the video with the url, see
+    /// `youtube_watch_url`.
+    pub fn playback_url(&self, url: &str) -> String {
+        match (&self.youtube_watch_url, feeds::youtube_video_id(url)) {
+            (Some(template), Some(id)) => template.replace("{id}", id),
+            _ => url.to_owned(),
+        }
+    }
+
+    /// The canonical youtube url of videos that are linked via the front-end of
+    /// `youtube_watch_url`, so that they are the same videos as those of youtube feeds.
+    pub fn canonical_url(&self, url: &str) -> String {
+        let prefix = self
+            .youtube_watch_url
+            .as_deref()
+            .and_then(|template| template.split("{id}").next())
+            .filter(|prefix| !prefix.is_empty());
+        match prefix.and_then(|prefix| url.strip_prefix(prefix)) {
+            Some(rest) => match rest.split(|c| c == '&' || c == '?' || c == '#').next() {
+                Some(id) if !id.is_empty() => feeds::youtube_video_url(id),
+                _ => url.to_owned(),
+            },
+            None => url.to_owned(),
+        }
+    }
+
+    /// Whether the title matches an entry of the blocklist.
+    pub fn is_blocked(&self, title: &str) -> bool {
+        self.blocklist.iter().any(|pattern| pattern.is_match(title))
+    }
+
+    /// Whether the entry of the feed should be skipped as a youtube short.
+    pub fn skip_short(&self, feed: &crate::data::Feed, entry: &crate::feeds::Entry) -> bool {
+        let skip = self
+            .feed(&feed.title)
+            .or_else(|| self.feed(&feed.url))
+            .and_then(|f| f.skip_shorts)
+            .unwrap_or(self.skip_shorts);
+        skip && (entry.short_link
+            || entry
+                .duration_secs
+                .map_or(false, |d| d <= self.shorts_max_duration_secs))
+    }
+
+    /// Whether the entry of the feed is shorter or longer than the feed's duration limits. Entries
+    /// without a duration are never skipped.
+    pub fn skip_duration(&self, feed: &crate::data::Feed, entry: &crate::feeds::Entry) -> bool {
+        let (feed_settings, duration) = match (
+            self.feed(&feed.title).or_else(|| self.feed(&feed.url)),
+            entry.duration_secs,
+        ) {
+            (Some(feed_settings), Some(duration)) => (feed_settings, duration),
+            _ => return false,
+        };
+        feed_settings
+            .min_duration_secs
+            .map_or(false, |min| duration < min)
+            || feed_settings
+                .max_duration_secs
+                .map_or(false, |max| duration > max)
+    }
+}
+
+pub fn default_database_file() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or(PathBuf::from("./"))
+        .join(DB_NAME)
+}
+
+pub fn default_mpv_socket() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("uvp-mpv.sock")
+}
+
+pub fn default_download_dir() -> PathBuf {
+    dirs::video_dir().unwrap_or(PathBuf::from("./")).join("uvp")
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            database_file: default_database_file(),
+            player_command: None,
+            mpv_binary: "mpv".to_owned(),
+            mpv_args: vec!["--force-window=immediate".to_owned()],
+            reuse_mpv: false,
+            mpv_socket: default_mpv_socket(),
+            quality: None,
+            resume_rewind_secs: 5.0,
+            resume_at_chapter: false,
+            completion_threshold: CompletionThreshold::SecondsFromEnd(1.0),
+            min_position_to_save_secs: 30.0,
+            position_save_interval_secs: 10,
+            proxy: None,
+            fetch_retries: 2,
+            fetch_retry_delay_secs: 1.0,
+            refresh_freshness_secs: 0,
+            youtube_feed_url: None,
+            youtube_watch_url: None,
+            browser_binary: "xdg-open".to_owned(),
+            clipboard_command: "xclip -selection clipboard".to_owned(),
+            picker_command: "fzf".to_owned(),
+            probe_durations: false,
+            download_binary: "yt-dlp".to_owned(),
+            download_dir: default_download_dir(),
+            download_concurrency: 2,
+            download_max_bytes: None,
+            download_keep_days: None,
+            skip_shorts: false,
+            shorts_max_duration_secs: 60.0,
+            theme: Theme::default(),
+            date_format: tui::DateFormat::Relative,
+            layout: tui::Split::Horizontal,
+            layout_ratio: 0.5,
+            active_order: ActiveOrder::Insertion,
+            hooks: Hooks::default(),
+            feeds: HashMap::new(),
+            blocklist: Vec::new(),
+            aliases: HashMap::new(),
+            default_command: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub(crate) primary_fg: Color,
+    pub(crate) primary_bg: Color,
+    pub(crate) alt_fg: Color,
+    pub(crate) alt_bg: Color,
+    pub(crate) separator_fg: Color,
+    pub(crate) highlight_fg: Color,
+    pub(crate) status_fg: Color,
+    pub(crate) status_bg: Color,
+    /// Colors of the focused row. If both are "default", the row is inverted instead.
+    pub(crate) selection_fg: Color,
+    pub(crate) selection_bg: Color,
+    pub(crate) focus_border: Color,
+    /// Marker and title of available videos that are new since the previous tui session.
+    pub(crate) new_fg: Color,
+    /// Draw the titles of feeds in colors of `FEED_PALETTE` (see `Settings::feed_color`).
+    pub(crate) feed_colors: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            primary_fg: Color::Default,
+            primary_bg: Color::Default,
+            alt_fg: Color::Default,
+            alt_bg: Color::Ansi(8),
+            separator_fg: Color::Default,
+            highlight_fg: Color::Yellow,
+            status_fg: Color::Default,
+            status_bg: Color::Default,
+            selection_fg: Color::Default,
+            selection_bg: Color::Default,
+            focus_border: Color::Yellow,
+            new_fg: Color::Green,
+            feed_colors: true,
+        }
+    }
+}
+
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("white", Color::White),
+    ("light_black", Color::LightBlack),
+    ("light_red", Color::LightRed),
+    ("light_green", Color::LightGreen),
+    ("light_yellow", Color::LightYellow),
+    ("light_blue", Color::LightBlue),
+    ("light_magenta", Color::LightMagenta),
+    ("light_cyan", Color::LightCyan),
+    ("light_white", Color::LightWhite),
+];
+
+/// Colors of the feeds in the tui, which work with light and dark terminal themes alike.
+const FEED_PALETTE: &[Color] = &[
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+];
+
+/// Parse "default", a color name, a "#rrggbb" hex value or an ansi color number.
+fn parse_color(s: &str) -> Result<Color, Error> {
+    if s == "default" {
+        return Ok(Color::Default);
+    }
+    if let Some((_, c)) = NAMED_COLORS.iter().find(|(name, _)| *name == s) {
+        return Ok(*c);
+    }
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() == 6 && hex.is_ascii() {
+            return Ok(Color::Rgb {
+                r: u8::from_str_radix(&hex[0..2], 16)?,
+                g: u8::from_str_radix(&hex[2..4], 16)?,
+                b: u8::from_str_radix(&hex[4..6], 16)?,
+            });
+        }
+    }
+    s.parse::<u8>().map(Color::Ansi).map_err(|_| {
+        Error::Config(config::ConfigError::Message(format!(
+            "Invalid color '{}'",
+            s
+        )))
+    })
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+impl Theme {
+    fn preset(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "dark" => Some(Theme {
+                primary_fg: rgb(0xd0, 0xd0, 0xd0),
+                primary_bg: rgb(0x1c, 0x1c, 0x1c),
+                alt_fg: rgb(0xd0, 0xd0, 0xd0),
+                alt_bg: rgb(0x30, 0x30, 0x30),
+                separator_fg: rgb(0x5f, 0x5f, 0x5f),
+                highlight_fg: rgb(0xff, 0xaf, 0x00),
+                status_fg: rgb(0xd0, 0xd0, 0xd0),
+                status_bg: rgb(0x3a, 0x3a, 0x3a),
+                selection_fg: rgb(0x1c, 0x1c, 0x1c),
+                selection_bg: rgb(0x87, 0xaf, 0xd7),
+                focus_border: rgb(0xff, 0xaf, 0x00),
+                new_fg: rgb(0x87, 0xd7, 0x5f),
+                feed_colors: true,
+            }),
+            "light" => Some(Theme {
+                primary_fg: rgb(0x30, 0x30, 0x30),
+                primary_bg: rgb(0xfa, 0xfa, 0xfa),
+                alt_fg: rgb(0x30, 0x30, 0x30),
+                alt_bg: rgb(0xe4, 0xe4, 0xe4),
+                separator_fg: rgb(0xa8, 0xa8, 0xa8),
+                highlight_fg: rgb(0xaf, 0x5f, 0x00),
+                status_fg: rgb(0x30, 0x30, 0x30),
+                status_bg: rgb(0xd0, 0xd0, 0xd0),
+                selection_fg: rgb(0xfa, 0xfa, 0xfa),
+                selection_bg: rgb(0x00, 0x5f, 0x87),
+                focus_border: rgb(0xaf, 0x5f, 0x00),
+                new_fg: rgb(0x00, 0x87, 0x00),
+                feed_colors: true,
+            }),
+            "solarized" => Some(Theme {
+                primary_fg: rgb(0x83, 0x94, 0x96),
+                primary_bg: rgb(0x00, 0x2b, 0x36),
+                alt_fg: rgb(0x93, 0xa1, 0xa1),
+                alt_bg: rgb(0x07, 0x36, 0x42),
+                separator_fg: rgb(0x58, 0x6e, 0x75),
+                highlight_fg: rgb(0xb5, 0x89, 0x00),
+                status_fg: rgb(0x93, 0xa1, 0xa1),
+                status_bg: rgb(0x07, 0x36, 0x42),
+                selection_fg: rgb(0xfd, 0xf6, 0xe3),
+                selection_bg: rgb(0x26, 0x8b, 0xd2),
+                focus_border: rgb(0xb5, 0x89, 0x00),
+                new_fg: rgb(0x85, 0x99, 0x00),
+                feed_colors: true,
+            }),
+            "gruvbox" => Some(Theme {
+                primary_fg: rgb(0xeb, 0xdb, 0xb2),
+                primary_bg: rgb(0x28, 0x28, 0x28),
+                alt_fg: rgb(0xeb, 0xdb, 0xb2),
+                alt_bg: rgb(0x3c, 0x38, 0x36),
+                separator_fg: rgb(0x66, 0x5c, 0x54),
+                highlight_fg: rgb(0xfa, 0xbd, 0x2f),
+                status_fg: rgb(0xeb, 0xdb, 0xb2),
+                status_bg: rgb(0x50, 0x49, 0x45),
+                selection_fg: rgb(0x28, 0x28, 0x28),
+                selection_bg: rgb(0x83, 0xa5, 0x98),
+                focus_border: rgb(0xfa, 0xbd, 0x2f),
+                new_fg: rgb(0xb8, 0xbb, 0x26),
+                feed_colors: true,
+            }),
+            _ => None,
+        }
+    }
+
+    const KEYS: &'static [&'static str] = &[
+        "primary_fg",
+        "primary_bg",
+        "alt_fg",
+        "alt_bg",
+        "separator_fg",
+        "highlight_fg",
+        "status_fg",
+        "status_bg",
+        "selection_fg",
+        "selection_bg",
+        "focus_border",
+        "new_fg",
+    ];
+}
+
+impl TryFrom<config::Map<String, config::Value>> for Theme {
+    type Error = Error;
+
+    fn try_from(value: config::Map<String, config::Value>) -> Result<Self, Self::Error> {
+        let mut theme = if let Some(preset) = value.get("preset") {
+            let preset = preset.clone().into_string()?;
+            Theme::preset(&preset).ok_or_else(|| {
+                config::ConfigError::Message(format!("Unknown theme preset '{}'", preset))
+            })?
+        } else {
+            Theme::default()
+        };
+
+        if let Some(feed_colors) = value.get("feed_colors") {
+            theme.feed_colors = feed_colors.clone().into_bool()?;
+        }
+        for key in Self::KEYS {
+            if let Ok(v) = value
+                .get(*key)
+                .ok_or(config::ConfigError::NotFound(key.to_string()))
+                .and_then(|v| v.clone().into_string())
+            {
+                let value = parse_color(&v)?;
+
+                match *key {
+                    "primary_fg" => theme.primary_fg = value,
+                    "primary_bg" => theme.primary_bg = value,
+                    "alt_fg" => theme.alt_fg = value,
+                    "alt_bg" => theme.alt_bg = value,
+                    "separator_fg" => theme.separator_fg = value,
+                    "highlight_fg" => theme.highlight_fg = value,
+                    "status_fg" => theme.status_fg = value,
+                    "status_bg" => theme.status_bg = value,
+                    "selection_fg" => theme.selection_fg = value,
+                    "selection_bg" => theme.selection_bg = value,
+                    "focus_border" => theme.focus_border = value,
+                    "new_fg" => theme.new_fg = value,
+                    _ => continue,
+                }
+            }
+        }
+
+        Ok(theme)
+    }
+}
@@ -0,0 +1,156 @@
+//! Aligned output of the list commands.
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const SEPARATOR: &str = " | ";
+/// Shrinkable columns are not truncated below this width.
+const MIN_SHRUNK_WIDTH: usize = 12;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+pub struct Column {
+    header: &'static str,
+    align: Align,
+    /// Whether the column is truncated if the table is wider than the terminal.
+    shrink: bool,
+}
+
+impl Column {
+    pub fn new(header: &'static str) -> Self {
+        Column {
+            header,
+            align: Align::Left,
+            shrink: false,
+        }
+    }
+
+    pub fn right(mut self) -> Self {
+        self.align = Align::Right;
+        self
+    }
+
+    pub fn shrink(mut self) -> Self {
+        self.shrink = true;
+        self
+    }
+}
+
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        assert_eq!(cells.len(), self.columns.len(), "one cell per column");
+        self.rows.push(cells);
+    }
+
+    /// Print the table to stdout. On a terminal, the table is fit to its width and the header is
+    /// bold (unless `NO_COLOR` is set).
+    pub fn print(&self) {
+        let stdout = std::io::stdout();
+        let tty = termion::is_tty(&stdout);
+        let mut widths = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                self.rows
+                    .iter()
+                    .map(|r| r[i].width())
+                    .chain(std::iter::once(c.header.width()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        if tty {
+            if let Ok((terminal_width, _)) = termion::terminal_size() {
+                self.fit(&mut widths, terminal_width as usize);
+            }
+        }
+        let bold = tty && std::env::var_os("NO_COLOR").is_none();
+        let header = self.format_row(&widths, self.columns.iter().map(|c| c.header));
+        if bold {
+            println!(
+                "{}{}{}",
+                termion::style::Bold,
+                header,
+                termion::style::Reset
+            );
+        } else {
+            println!("{}", header);
+        }
+        for row in &self.rows {
+            println!(
+                "{}",
+                self.format_row(&widths, row.iter().map(|c| c.as_str()))
+            );
+        }
+    }
+
+    /// Shrink the widest shrinkable columns until the table fits into the width, if possible.
+    fn fit(&self, widths: &mut [usize], width: usize) {
+        let total = |widths: &[usize]| {
+            widths.iter().sum::<usize>() + SEPARATOR.len() * widths.len().saturating_sub(1)
+        };
+        while total(widths) > width {
+            let widest = (0..widths.len())
+                .filter(|&i| self.columns[i].shrink && widths[i] > MIN_SHRUNK_WIDTH)
+                .max_by_key(|&i| widths[i]);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    fn format_row<'a>(&self, widths: &[usize], cells: impl Iterator<Item = &'a str>) -> String {
+        let last = self.columns.len() - 1;
+        let cells = cells
+            .zip(widths)
+            .zip(&self.columns)
+            .enumerate()
+            .map(|(i, ((cell, &width), column))| {
+                let cell = truncate(cell, width);
+                let padding = " ".repeat(width - cell.width());
+                match column.align {
+                    Align::Right => padding + &cell,
+                    // No trailing whitespace at the end of the line.
+                    Align::Left if i == last => cell,
+                    Align::Left => cell + &padding,
+                }
+            })
+            .collect::<Vec<_>>();
+        cells.join(SEPARATOR)
+    }
+}
+
+/// The string, shortened with an ellipsis if it is wider than `width`.
+fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    let mut res = String::new();
+    let mut res_width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if res_width + w + 1 > width {
+            break;
+        }
+        res.push(c);
+        res_width += w;
+    }
+    res.push('…');
+    res
+}
@@ -1,55 +1,248 @@
 use crate::data::{
-    add_to_active, add_to_available, iter_active, iter_available, remove_from_active,
-    remove_from_available,
+    add_to_feed, add_to_filter_history, delete_video, enqueue_download, feed_error, feeds_of_entry,
+    filter_history, find_feed, find_in_active, find_in_available, iter_active, iter_available,
+    iter_downloads, iter_feeds, last_deleted, last_tui_session, lastfetched, most_recently_played,
+    now_playing, position_update, restore_deleted, set_feed_auth, set_last_tui_session,
+    set_priority, Download, DownloadState, Feed,
+};
+use crate::download::Downloader;
+use crate::probe::Prober;
+use crate::{
+    format_secs, ignore_constraint_errors, refresh, refresh_feeds, AddFeed, Settings,
+    StartupProfile, Theme,
 };
-use crate::{refresh, Theme};
 use rusqlite::Connection;
 use signal_hook::iterator::Signals;
-use unsegen::base::{Color, GraphemeCluster, StyleModifier, Window};
-use unsegen::container::{Container, ContainerManager, ContainerProvider, HSplit, Leaf};
-use unsegen::input::ScrollBehavior;
-use unsegen::input::{Input, Key, NavigateBehavior};
+use std::cell::Cell;
+use std::fmt::Write as _;
+use structopt::StructOpt;
+use unsegen::base::{Color, StyleModifier, Window};
+use unsegen::container::{
+    Container, ContainerManager, ContainerProvider, HSplit, Layout, Leaf, VSplit,
+};
+use unsegen::input::{Behavior, Event, Input, Key, Navigatable, NavigateBehavior, Scrollable};
+use unsegen::input::{EditBehavior, ScrollBehavior, WriteBehavior};
 use unsegen::widget::{
-    builtin::{Column, Table, TableRow},
-    ColDemand, Demand2D, RenderingHints, SeparatingStyle, Widget, WidgetExt,
+    builtin::{Column, LogViewer, PromptLine, Table, TableRow},
+    ColDemand, Demand2D, RenderingHints, SeparatingStyle, VLayout, Widget, WidgetExt,
 };
 
 use chrono::Duration;
 
-use crate::data::{Active, Available};
+use crate::data::{Active, Available, DateTime};
+
+/// How publication dates are shown in the tui.
+pub enum DateFormat {
+    /// "3h ago", "2 days ago", ...
+    Relative,
+    /// A chrono/strftime format string, e.g. "%Y-%m-%d %H:%M".
+    Custom(String),
+}
 
-fn format_duration_secs(duration: f64) -> String {
-    format_duration(Duration::milliseconds((duration * 1_000.0) as i64))
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relative" => Ok(DateFormat::Relative),
+            "rfc3339" => Ok(DateFormat::Custom("%+".to_owned())),
+            o => Ok(DateFormat::Custom(o.to_owned())),
+        }
+    }
 }
-fn format_duration(mut duration: Duration) -> String {
-    let prefix = if duration < Duration::zero() {
-        duration = -duration;
-        "-"
+
+/// How the active and available lists are arranged.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Split {
+    /// Side by side.
+    Horizontal,
+    /// Active above available.
+    Vertical,
+}
+
+impl std::str::FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "horizontal" => Ok(Split::Horizontal),
+            "vertical" => Ok(Split::Vertical),
+            o => Err(format!("Invalid layout '{}' (horizontal or vertical)", o)),
+        }
+    }
+}
+
+/// The current arrangement of the panes, which can be changed at runtime.
+struct PaneLayout {
+    split: Split,
+    /// Share of the active list of the space of both lists.
+    ratio: f64,
+    /// The only pane that is shown, if any.
+    zoomed: Option<TuiComponents>,
+}
+
+impl PaneLayout {
+    fn build<'a>(&self) -> Box<dyn Layout<Tui<'a>> + 'a> {
+        if let Some(pane) = &self.zoomed {
+            return Box::new(Leaf::new(pane.clone()));
+        }
+        let lists: Vec<(Box<dyn Layout<Tui>>, f64)> = vec![
+            (Box::new(Leaf::new(TuiComponents::Active)), self.ratio),
+            (
+                Box::new(Leaf::new(TuiComponents::Available)),
+                1.0 - self.ratio,
+            ),
+        ];
+        match self.split {
+            Split::Horizontal => Box::new(VSplit::new(vec![
+                (Box::new(HSplit::new(lists)), 4.0),
+                (Box::new(Leaf::new(TuiComponents::Downloads)), 1.0),
+            ])),
+            Split::Vertical => {
+                let mut panes = lists
+                    .into_iter()
+                    .map(|(pane, weight)| (pane, weight * 4.0))
+                    .collect::<Vec<_>>();
+                panes.push((Box::new(Leaf::new(TuiComponents::Downloads)), 1.0));
+                Box::new(VSplit::new(panes))
+            }
+        }
+    }
+}
+
+/// Step by which `<` and `>` change the share of the active list.
+const RATIO_STEP: f64 = 0.1;
+
+impl DateFormat {
+    fn format(&self, date: &DateTime) -> String {
+        match self {
+            DateFormat::Relative => format_relative(*date, chrono::Local::now().into()),
+            DateFormat::Custom(format) => date.format(format).to_string(),
+        }
+    }
+}
+
+fn format_relative(date: DateTime, now: DateTime) -> String {
+    let age = now - date;
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", n, unit)
+        }
+    };
+    if age < Duration::minutes(1) {
+        "just now".to_owned()
+    } else if age < Duration::hours(1) {
+        format!("{}m ago", age.num_minutes())
+    } else if age < Duration::days(1) {
+        format!("{}h ago", age.num_hours())
+    } else if age < Duration::days(60) {
+        plural(age.num_days(), "day")
+    } else if age < Duration::days(365) {
+        plural(age.num_days() / 30, "month")
+    } else {
+        plural(age.num_days() / 365, "year")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SortKey {
+    Date,
+    Title,
+    Feed,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(SortKey::Date),
+            "title" => Ok(SortKey::Title),
+            "feed" => Ok(SortKey::Feed),
+            o => Err(format!("Unknown sort key '{}' (date, title or feed)", o)),
+        }
+    }
+}
+
+/// Filter and sort order applied to both tables.
+struct View {
+    filter: String,
+    /// Only show entries of the feed with this title.
+    feed: Option<String>,
+    sort: SortKey,
+}
+
+impl View {
+    fn matches(&self, feed_title: &str, fields: &[&str]) -> bool {
+        if self
+            .feed
+            .as_deref()
+            .map_or(false, |feed| feed != feed_title)
+        {
+            return false;
+        }
+        let filter = self.filter.to_lowercase();
+        std::iter::once(&feed_title)
+            .chain(fields)
+            .any(|f| f.to_lowercase().contains(&filter))
+    }
+}
+
+fn highlight_active(theme: &Theme) -> impl Fn(Window, RenderingHints) -> Window {
+    let style = if theme.selection_fg == Color::Default && theme.selection_bg == Color::Default {
+        StyleModifier::new().invert(true).bold(true)
     } else {
-        " "
+        StyleModifier::new()
+            .fg_color(theme.selection_fg)
+            .bg_color(theme.selection_bg)
+            .bold(true)
     };
-    let minutes = duration.num_minutes();
-    duration = duration - Duration::minutes(minutes);
-    let seconds = duration.num_seconds();
-    duration = duration - Duration::seconds(seconds);
-    let millis = duration.num_milliseconds();
-    format!("{}{:>2}:{:02}.{:03}", prefix, minutes, seconds, millis)
+    move |mut window, hints| {
+        if hints.active {
+            window.set_default_style(style.apply_to_default());
+        }
+        window
+    }
 }
 
-fn highlight_active(mut window: Window, hints: RenderingHints) -> Window {
-    if hints.active {
-        window.set_default_style(
-            StyleModifier::new()
-                .invert(true)
-                .bold(true)
-                .apply_to_default(),
-        );
+/// Entries that are new since the previous session are drawn in their own color (unless focused).
+fn highlight_new(theme: &Theme, new: bool) -> impl Fn(Window, RenderingHints) -> Window {
+    let style = StyleModifier::new().fg_color(theme.new_fg);
+    move |mut window, _| {
+        if new {
+            window.modify_default_style(style);
+        }
+        window
     }
-    window
+}
+
+/// The title of a feed in its color (see `Settings::feed_color`), unless focused.
+fn feed_color(color: Option<Color>) -> impl Fn(Window, RenderingHints) -> Window {
+    move |mut window, _| {
+        if let Some(color) = color {
+            window.modify_default_style(StyleModifier::new().fg_color(color));
+        }
+        window
+    }
+}
+
+/// Column separators are separate columns so that they can be styled via the theme.
+fn separator(theme: &Theme) -> Box<dyn Widget + '_> {
+    let style = StyleModifier::new().fg_color(theme.separator_fg);
+    Box::new("|".with_window(move |mut w, _| {
+        w.modify_default_style(style);
+        w
+    }))
 }
 
 struct ActiveRow {
+    theme: Theme,
+    /// Position in the table, for the position indicator.
+    index: usize,
     source: String,
+    source_color: Option<Color>,
     title: String,
     time: String,
     data: Active,
@@ -59,21 +252,39 @@ impl TableRow for ActiveRow {
     type BehaviorContext = ();
     const COLUMNS: &'static [Column<ActiveRow>] = &[
         Column {
-            access: |r| Box::new(r.source.as_str().with_window(highlight_active)),
+            access: |r| {
+                Box::new(
+                    r.source
+                        .as_str()
+                        .with_window(highlight_active(&r.theme))
+                        .with_window(feed_color(r.source_color)),
+                )
+            },
             behavior: |_, i, _| Some(i),
         },
         Column {
-            access: |r| Box::new(r.title.as_str().with_window(highlight_active)),
+            access: |r| separator(&r.theme),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| Box::new(r.title.as_str().with_window(highlight_active(&r.theme))),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| separator(&r.theme),
             behavior: |_, i, _| Some(i),
         },
         Column {
             access: |r| {
-                Box::new(r.time.as_str().with_window(highlight_active).with_demand(
-                    |d: Demand2D| Demand2D {
-                        width: ColDemand::at_least(d.width.min),
-                        height: d.height,
-                    },
-                ))
+                Box::new(
+                    r.time
+                        .as_str()
+                        .with_window(highlight_active(&r.theme))
+                        .with_demand(|d: Demand2D| Demand2D {
+                            width: ColDemand::at_least(d.width.min),
+                            height: d.height,
+                        }),
+                )
             },
             behavior: |_, i, _| Some(i),
         },
@@ -82,43 +293,82 @@ impl TableRow for ActiveRow {
 
 struct ActiveTable<'t> {
     table: Table<ActiveRow>,
-    deleted: Vec<Active>,
+    pager: Pager,
+    entries: Vec<Active>,
     theme: &'t Theme,
+    settings: &'t Settings,
 }
 
 impl<'t> ActiveTable<'t> {
-    fn with_active(active: impl Iterator<Item = Active>, theme: &'t Theme) -> Self {
+    fn with_active(active: Vec<Active>, view: &View, settings: &'t Settings) -> Self {
         let mut tui = ActiveTable {
             table: Table::new(),
-            deleted: Vec::new(),
-            theme,
+            pager: Pager::default(),
+            entries: Vec::new(),
+            theme: &settings.theme,
+            settings,
         };
-        tui.update(active);
+        tui.update(active, view);
         tui
     }
 
-    fn update(&mut self, active: impl Iterator<Item = Active>) {
+    fn update(&mut self, active: Vec<Active>, view: &View) {
+        self.entries = active;
+        self.rebuild(view);
+    }
+
+    fn rebuild(&mut self, view: &View) {
+        let mut active = self
+            .entries
+            .iter()
+            .filter(|a| {
+                view.matches(
+                    a.feed_title.as_deref().unwrap_or(""),
+                    &[a.title.as_deref().unwrap_or(""), &a.url],
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        match view.sort {
+            // Active entries do not have a publication date, so we keep the database order.
+            SortKey::Date => {}
+            SortKey::Title => active.sort_by(|l, r| l.title.cmp(&r.title)),
+            SortKey::Feed => active.sort_by(|l, r| l.feed_title.cmp(&r.feed_title)),
+        }
+
+        self.pager.rows = active.len();
+        let settings = self.settings;
         let mut rows = self.table.rows_mut();
         rows.clear();
-        for active in active {
+        for (index, active) in active.into_iter().enumerate() {
             rows.push(ActiveRow {
+                theme: *self.theme,
+                index,
                 source: active
                     .feed_title
                     .as_deref()
                     .unwrap_or("External")
                     .to_owned(),
+                source_color: active
+                    .feed_title
+                    .as_deref()
+                    .and_then(|t| settings.feed_color(t)),
                 title: active.title.as_deref().unwrap_or("Unknown").to_owned(),
                 time: {
                     let label = if let Some(duration_secs) = active.duration_secs {
-                        let progress_str = format_duration_secs(active.position_secs);
-                        let duration_str = format_duration_secs(duration_secs);
+                        let progress_str = format_secs(active.position_secs);
+                        let duration_str = format_secs(duration_secs);
                         let percentage = (active.position_secs / duration_secs * 100.0) as u32;
                         format!("{}/{} ({}%)", progress_str, duration_str, percentage)
                     } else {
-                        format_duration_secs(active.position_secs)
+                        format_secs(active.position_secs)
                     };
 
-                    label
+                    if let (Some(chapter), Some(count)) = (active.chapter, active.chapter_count) {
+                        format!("{} Chapter {}/{}", label, chapter + 1, count)
+                    } else {
+                        label
+                    }
                 },
                 data: active,
             });
@@ -135,20 +385,74 @@ impl Container<<Tui<'_> as ContainerProvider>::Context> for ActiveTable<'_> {
         input
             .chain((Key::Char('\n'), || {
                 if let Some(row) = self.table.current_row() {
-                    sender.send(TuiMsg::Play(row.data.url.clone())).unwrap();
+                    sender
+                        .send(TuiMsg::Play(row.data.url.clone(), false))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('a'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender
+                        .send(TuiMsg::Play(row.data.url.clone(), true))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('s'), || {
+                if let Some(row) = self.table.current_row() {
+                    let msg = TuiMsg::Download(row.data.url.clone(), row.data.title.clone());
+                    sender.send(msg).unwrap();
+                }
+            }))
+            .chain((Key::Char('o'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender.send(TuiMsg::Open(row.data.url.clone())).unwrap();
+                }
+            }))
+            .chain((Key::Char('y'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender.send(TuiMsg::Copy(row.data.url.clone())).unwrap();
+                }
+            }))
+            .chain((Key::Char('i'), || {
+                if let Some(row) = self.table.current_row() {
+                    let last_played = row
+                        .data
+                        .last_played
+                        .map(|l| format!("last played {}", l.to_rfc3339()))
+                        .unwrap_or("never played".to_owned());
+                    let info = format!("{} ({})", row.data.url, last_played);
+                    sender
+                        .send(TuiMsg::ActiveInfo(row.data.url.clone(), info))
+                        .unwrap();
                 }
             }))
             .chain((Key::Char('d'), || {
                 if let Some(row) = self.table.current_row() {
-                    self.deleted.push(row.data.clone());
                     sender.send(TuiMsg::Delete(row.data.url.clone())).unwrap();
                 }
             }))
             .chain((&[Key::Char('u'), Key::Delete][..], || {
-                if let Some(a) = self.deleted.pop() {
-                    sender.send(TuiMsg::AddActive(a)).unwrap();
+                sender.send(TuiMsg::Undelete(true)).unwrap();
+            }))
+            .chain((Key::Char('F'), || {
+                if let Some(row) = self.table.current_row() {
+                    let feed = row.data.feed_title.clone().unwrap_or_default();
+                    sender.send(TuiMsg::FilterFeed(feed)).unwrap();
                 }
             }))
+            .chain((Key::Char('+'), || {
+                if let Some(row) = self.table.current_row() {
+                    let msg = TuiMsg::SetPriority(row.data.url.clone(), row.data.priority + 1);
+                    sender.send(msg).unwrap();
+                }
+            }))
+            .chain((Key::Char('-'), || {
+                if let Some(row) = self.table.current_row() {
+                    let msg = TuiMsg::SetPriority(row.data.url.clone(), row.data.priority - 1);
+                    sender.send(msg).unwrap();
+                }
+            }))
+            .chain(self.pager.behavior(&mut self.table))
             .chain(
                 NavigateBehavior::new(&mut self.table)
                     .up_on(Key::Char('k'))
@@ -165,33 +469,39 @@ impl Container<<Tui<'_> as ContainerProvider>::Context> for ActiveTable<'_> {
     }
 
     fn as_widget<'a>(&'a self) -> Box<dyn Widget + 'a> {
-        Box::new(
-            self.table
-                .as_widget()
-                .row_separation(SeparatingStyle::AlternatingStyle(
+        let table = self
+            .table
+            .as_widget()
+            .row_separation(SeparatingStyle::AlternatingStyle(
+                StyleModifier::new()
+                    .bg_color(self.theme.alt_bg)
+                    .fg_color(self.theme.alt_fg),
+            ))
+            .with_window(move |mut w, _| {
+                self.pager.set_height(&w);
+                w.set_default_style(
                     StyleModifier::new()
-                        .bg_color(self.theme.alt_bg)
-                        .fg_color(self.theme.alt_fg),
-                ))
-                .col_separation(SeparatingStyle::Draw(
-                    GraphemeCluster::try_from('|').unwrap(),
-                ))
-                .with_window(move |mut w, _| {
-                    w.set_default_style(
-                        StyleModifier::new()
-                            .fg_color(self.theme.primary_fg)
-                            .bg_color(self.theme.primary_bg)
-                            .apply_to_default(),
-                    );
-                    w
-                }),
-        )
+                        .fg_color(self.theme.primary_fg)
+                        .bg_color(self.theme.primary_bg)
+                        .apply_to_default(),
+                );
+                w
+            });
+        let position = self.table.current_row().map(|r| r.index);
+        Box::new(self.pager.with_indicator(table, position, self.theme))
     }
 }
 
 struct AvailableRow {
+    theme: Theme,
+    /// Position in the table, for the position indicator.
+    index: usize,
+    /// Added since the previous session.
+    new: bool,
     source: String,
+    source_color: Option<Color>,
     title: String,
+    duration: String,
     publication: String,
     data: Available,
 }
@@ -200,11 +510,61 @@ impl TableRow for AvailableRow {
     type BehaviorContext = ();
     const COLUMNS: &'static [Column<AvailableRow>] = &[
         Column {
-            access: |r| Box::new(r.source.as_str().with_window(highlight_active)),
+            access: |r| {
+                Box::new(
+                    (if r.new { "N" } else { "" })
+                        .with_window(highlight_active(&r.theme))
+                        .with_window(highlight_new(&r.theme, r.new)),
+                )
+            },
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| {
+                Box::new(
+                    r.source
+                        .as_str()
+                        .with_window(highlight_active(&r.theme))
+                        .with_window(feed_color(r.source_color)),
+                )
+            },
             behavior: |_, i, _| Some(i),
         },
         Column {
-            access: |r| Box::new(r.title.as_str().with_window(highlight_active)),
+            access: |r| separator(&r.theme),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| {
+                Box::new(
+                    r.title
+                        .as_str()
+                        .with_window(highlight_active(&r.theme))
+                        .with_window(highlight_new(&r.theme, r.new)),
+                )
+            },
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| separator(&r.theme),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| {
+                Box::new(
+                    r.duration
+                        .as_str()
+                        .with_window(highlight_active(&r.theme))
+                        .with_demand(|d: Demand2D| Demand2D {
+                            width: ColDemand::at_least(d.width.min),
+                            height: d.height,
+                        }),
+                )
+            },
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| separator(&r.theme),
             behavior: |_, i, _| Some(i),
         },
         Column {
@@ -212,7 +572,7 @@ impl TableRow for AvailableRow {
                 Box::new(
                     r.publication
                         .as_str()
-                        .with_window(highlight_active)
+                        .with_window(highlight_active(&r.theme))
                         .with_demand(|d: Demand2D| Demand2D {
                             width: ColDemand::at_least(d.width.min),
                             height: d.height,
@@ -226,28 +586,67 @@ impl TableRow for AvailableRow {
 
 struct AvailableTable<'t> {
     table: Table<AvailableRow>,
-    deleted: Vec<Available>,
+    pager: Pager,
+    entries: Vec<Available>,
+    /// Start of the previous session, see `AvailableRow::new`.
+    new_since: Option<DateTime>,
     theme: &'t Theme,
+    date_format: &'t DateFormat,
+    settings: &'t Settings,
 }
 
 impl<'t> AvailableTable<'t> {
-    fn with_available(available: impl Iterator<Item = Available>, theme: &'t Theme) -> Self {
+    fn with_available(
+        available: Vec<Available>,
+        view: &View,
+        settings: &'t Settings,
+        new_since: Option<DateTime>,
+    ) -> Self {
         let mut tui = AvailableTable {
             table: Table::new(),
-            deleted: Vec::new(),
-            theme,
+            pager: Pager::default(),
+            entries: Vec::new(),
+            new_since,
+            theme: &settings.theme,
+            date_format: &settings.date_format,
+            settings,
         };
-        tui.update(available);
+        tui.update(available, view);
         tui
     }
-    fn update(&mut self, available: impl Iterator<Item = Available>) {
+    fn update(&mut self, available: Vec<Available>, view: &View) {
+        self.entries = available;
+        self.rebuild(view);
+    }
+    fn rebuild(&mut self, view: &View) {
+        let mut available = self
+            .entries
+            .iter()
+            .filter(|a| view.matches(&a.feed.title, &[&a.title, &a.url]))
+            .cloned()
+            .collect::<Vec<_>>();
+        match view.sort {
+            SortKey::Date => available.sort_by(|l, r| r.publication.cmp(&l.publication)),
+            SortKey::Title => available.sort_by(|l, r| l.title.cmp(&r.title)),
+            SortKey::Feed => available.sort_by(|l, r| l.feed.title.cmp(&r.feed.title)),
+        }
+
+        self.pager.rows = available.len();
         let mut rows = self.table.rows_mut();
         rows.clear();
-        for available in available {
+        for (index, available) in available.into_iter().enumerate() {
             rows.push(AvailableRow {
+                theme: *self.theme,
+                index,
+                new: match (self.new_since, available.added) {
+                    (Some(since), Some(added)) => added > since,
+                    _ => false,
+                },
                 source: available.feed.title.clone(),
+                source_color: self.settings.feed_color(&available.feed.title),
                 title: available.title.clone(),
-                publication: available.publication.to_rfc3339(),
+                duration: available.duration_secs.map(format_secs).unwrap_or_default(),
+                publication: self.date_format.format(&available.publication),
                 data: available,
             });
         }
@@ -263,20 +662,68 @@ impl Container<<Tui<'_> as ContainerProvider>::Context> for AvailableTable<'_> {
         input
             .chain((Key::Char('\n'), || {
                 if let Some(row) = self.table.current_row() {
-                    sender.send(TuiMsg::Play(row.data.url.clone())).unwrap();
+                    sender
+                        .send(TuiMsg::Play(row.data.url.clone(), false))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('a'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender
+                        .send(TuiMsg::Play(row.data.url.clone(), true))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('p'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender
+                        .send(TuiMsg::PlayFeed(row.data.feed.url.clone()))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('s'), || {
+                if let Some(row) = self.table.current_row() {
+                    let msg = TuiMsg::Download(row.data.url.clone(), Some(row.data.title.clone()));
+                    sender.send(msg).unwrap();
+                }
+            }))
+            .chain((Key::Char('o'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender.send(TuiMsg::Open(row.data.url.clone())).unwrap();
+                }
+            }))
+            .chain((Key::Char('y'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender.send(TuiMsg::Copy(row.data.url.clone())).unwrap();
+                }
+            }))
+            .chain((Key::Char('i'), || {
+                if let Some(row) = self.table.current_row() {
+                    let info = format!(
+                        "{} (published {})",
+                        row.data.url,
+                        row.data.publication.to_rfc3339()
+                    );
+                    sender
+                        .send(TuiMsg::AvailableInfo(row.data.url.clone(), info))
+                        .unwrap();
                 }
             }))
             .chain((Key::Char('d'), || {
                 if let Some(row) = self.table.current_row() {
-                    self.deleted.push(row.data.clone());
                     sender.send(TuiMsg::Delete(row.data.url.clone())).unwrap();
                 }
             }))
             .chain((Key::Char('u'), || {
-                if let Some(a) = self.deleted.pop() {
-                    sender.send(TuiMsg::AddAvailable(a)).unwrap();
+                sender.send(TuiMsg::Undelete(false)).unwrap();
+            }))
+            .chain((Key::Char('F'), || {
+                if let Some(row) = self.table.current_row() {
+                    let feed = row.data.feed.title.clone();
+                    sender.send(TuiMsg::FilterFeed(feed)).unwrap();
                 }
             }))
+            .chain(self.pager.behavior(&mut self.table))
             .chain(
                 NavigateBehavior::new(&mut self.table)
                     .up_on(Key::Char('k'))
@@ -293,52 +740,381 @@ impl Container<<Tui<'_> as ContainerProvider>::Context> for AvailableTable<'_> {
     }
 
     fn as_widget<'a>(&'a self) -> Box<dyn Widget + 'a> {
-        Box::new(
-            self.table
-                .as_widget()
-                .row_separation(SeparatingStyle::AlternatingStyle(
+        let table = self
+            .table
+            .as_widget()
+            .row_separation(SeparatingStyle::AlternatingStyle(
+                StyleModifier::new()
+                    .bg_color(self.theme.alt_bg)
+                    .fg_color(self.theme.alt_fg),
+            ))
+            .with_window(move |mut w, _| {
+                self.pager.set_height(&w);
+                w.set_default_style(
                     StyleModifier::new()
-                        .bg_color(self.theme.alt_bg)
-                        .fg_color(self.theme.alt_fg),
-                ))
-                .col_separation(SeparatingStyle::Draw(
-                    GraphemeCluster::try_from('|').unwrap(),
-                ))
-                .with_window(move |mut w, _| {
-                    w.set_default_style(
-                        StyleModifier::new()
-                            .fg_color(self.theme.primary_fg)
-                            .bg_color(self.theme.primary_bg)
-                            .apply_to_default(),
-                    );
-                    w
-                }),
-        )
+                        .fg_color(self.theme.primary_fg)
+                        .bg_color(self.theme.primary_bg)
+                        .apply_to_default(),
+                );
+                w
+            });
+        let position = self.table.current_row().map(|r| r.index);
+        Box::new(self.pager.with_indicator(table, position, self.theme))
+    }
+}
+
+struct DownloadRow {
+    theme: Theme,
+    /// Position in the table, for the position indicator.
+    index: usize,
+    title: String,
+    state: String,
+    data: Download,
+}
+
+impl TableRow for DownloadRow {
+    type BehaviorContext = ();
+    const COLUMNS: &'static [Column<DownloadRow>] = &[
+        Column {
+            access: |r| Box::new(r.title.as_str().with_window(highlight_active(&r.theme))),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| separator(&r.theme),
+            behavior: |_, i, _| Some(i),
+        },
+        Column {
+            access: |r| Box::new(r.state.as_str().with_window(highlight_active(&r.theme))),
+            behavior: |_, i, _| Some(i),
+        },
+    ];
+}
+
+struct DownloadsTable<'t> {
+    table: Table<DownloadRow>,
+    pager: Pager,
+    theme: &'t Theme,
+}
+
+impl<'t> DownloadsTable<'t> {
+    fn new(theme: &'t Theme) -> Self {
+        DownloadsTable {
+            table: Table::new(),
+            pager: Pager::default(),
+            theme,
+        }
+    }
+
+    fn update(&mut self, downloads: Vec<Download>, downloader: &Downloader) {
+        self.pager.rows = downloads.len();
+        let mut rows = self.table.rows_mut();
+        rows.clear();
+        for (index, download) in downloads.into_iter().enumerate() {
+            rows.push(DownloadRow {
+                theme: *self.theme,
+                index,
+                title: download.title.clone().unwrap_or(download.url.clone()),
+                state: match download.state {
+                    DownloadState::Queued => "queued".to_owned(),
+                    DownloadState::Running => match downloader.progress(&download.url) {
+                        Some(percent) => format!("{:.1}%", percent),
+                        None => "running".to_owned(),
+                    },
+                    DownloadState::Paused => "paused".to_owned(),
+                    DownloadState::Done => "done".to_owned(),
+                    DownloadState::Failed => format!(
+                        "failed: {}",
+                        download.error.as_deref().unwrap_or("unknown error")
+                    ),
+                },
+                data: download,
+            });
+        }
+    }
+}
+
+impl Container<<Tui<'_> as ContainerProvider>::Context> for DownloadsTable<'_> {
+    fn input(
+        &mut self,
+        input: Input,
+        sender: &mut <Tui as ContainerProvider>::Context,
+    ) -> Option<Input> {
+        input
+            .chain((Key::Char('\n'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender.send(TuiMsg::Jump(row.data.url.clone())).unwrap();
+                }
+            }))
+            .chain((Key::Char('p'), || {
+                if let Some(row) = self.table.current_row() {
+                    let msg = if row.data.state == DownloadState::Paused {
+                        TuiMsg::Download(row.data.url.clone(), row.data.title.clone())
+                    } else {
+                        TuiMsg::PauseDownload(row.data.url.clone())
+                    };
+                    sender.send(msg).unwrap();
+                }
+            }))
+            .chain((Key::Char('c'), || {
+                if let Some(row) = self.table.current_row() {
+                    sender
+                        .send(TuiMsg::CancelDownload(row.data.url.clone()))
+                        .unwrap();
+                }
+            }))
+            .chain((Key::Char('R'), || {
+                if let Some(row) = self.table.current_row() {
+                    if row.data.state == DownloadState::Failed {
+                        let msg = TuiMsg::Download(row.data.url.clone(), row.data.title.clone());
+                        sender.send(msg).unwrap();
+                    }
+                }
+            }))
+            .chain(self.pager.behavior(&mut self.table))
+            .chain(
+                NavigateBehavior::new(&mut self.table)
+                    .up_on(Key::Char('k'))
+                    .up_on(Key::Up)
+                    .down_on(Key::Char('j'))
+                    .down_on(Key::Down),
+            )
+            .chain(
+                ScrollBehavior::new(&mut self.table)
+                    .to_end_on(Key::Char('G'))
+                    .to_beginning_on(Key::Char('g')),
+            )
+            .finish()
+    }
+
+    fn as_widget<'a>(&'a self) -> Box<dyn Widget + 'a> {
+        let table = self
+            .table
+            .as_widget()
+            .row_separation(SeparatingStyle::AlternatingStyle(
+                StyleModifier::new()
+                    .bg_color(self.theme.alt_bg)
+                    .fg_color(self.theme.alt_fg),
+            ))
+            .with_window(move |mut w, _| {
+                self.pager.set_height(&w);
+                w.set_default_style(
+                    StyleModifier::new()
+                        .fg_color(self.theme.primary_fg)
+                        .bg_color(self.theme.primary_bg)
+                        .apply_to_default(),
+                );
+                w
+            });
+        let position = self.table.current_row().map(|r| r.index);
+        Box::new(self.pager.with_indicator(table, position, self.theme))
+    }
+}
+
+/// Page-wise navigation of a table and the position indicator below it.
+#[derive(Default)]
+struct Pager {
+    /// Number of rows of the table.
+    rows: usize,
+    /// Height of the table when it was last drawn.
+    height: Cell<usize>,
+}
+
+impl Pager {
+    fn set_height(&self, window: &Window) {
+        self.height
+            .set(window.get_height().raw_value().max(1) as usize);
+    }
+
+    /// PageUp/PageDown move the selection by a page, Ctrl-d/Ctrl-u by half a page.
+    fn behavior<'a, R: TableRow + 'static>(&self, table: &'a mut Table<R>) -> impl Behavior + 'a {
+        let page = self.height.get().max(1);
+        move |input: Input| {
+            let (rows, down) = match input.event {
+                Event::Key(Key::PageDown) => (page, true),
+                Event::Key(Key::PageUp) => (page, false),
+                Event::Key(Key::Ctrl('d')) => ((page / 2).max(1), true),
+                Event::Key(Key::Ctrl('u')) => ((page / 2).max(1), false),
+                _ => return Some(input),
+            };
+            for _ in 0..rows {
+                let moved = if down {
+                    table.move_down()
+                } else {
+                    table.move_up()
+                };
+                if moved.is_err() {
+                    break;
+                }
+            }
+            None
+        }
+    }
+
+    /// The table with a line like "17/243" below it.
+    fn with_indicator<'a>(
+        &self,
+        table: impl Widget + 'a,
+        position: Option<usize>,
+        theme: &Theme,
+    ) -> impl Widget + 'a {
+        let indicator = match position {
+            Some(position) => format!("{}/{}", position + 1, self.rows),
+            None => format!("-/{}", self.rows),
+        };
+        let style = StyleModifier::new().fg_color(theme.separator_fg);
+        VLayout::new()
+            .widget(table)
+            .widget(indicator.with_window(move |mut w, _| {
+                w.modify_default_style(style);
+                w
+            }))
+    }
+}
+
+/// Select the row of `url` in `table`. Returns whether the table contains the url.
+fn select_row<R: TableRow + 'static>(
+    table: &mut Table<R>,
+    url: &str,
+    row_url: impl Fn(&R) -> &str,
+) -> bool {
+    if let Some(pos) = table.rows().iter().position(|r| row_url(r) == url) {
+        let _ = table.scroll_to_beginning();
+        for _ in 0..pos {
+            let _ = table.move_down();
+        }
+        true
+    } else {
+        false
     }
 }
 
 enum Msg {
     Input(Input),
     Redraw,
+    /// Sent regularly to update the progress of downloads.
+    Tick,
 }
 enum TuiMsg {
-    Play(String),
+    /// Play the url, audio only if the flag is set.
+    Play(String, bool),
+    /// Play all available videos of the feed with the url.
+    PlayFeed(String),
+    Open(String),
+    Copy(String),
+    /// Info about the active video with the url, completed with where it has last been watched.
+    ActiveInfo(String, String),
+    /// Info about the available video with the url, completed with the feeds that carry it.
+    AvailableInfo(String, String),
     Delete(String),
-    AddActive(Active),
-    AddAvailable(Available),
+    /// Restore the most recently deleted active (or else available) video.
+    Undelete(bool),
+    SetPriority(String, i64),
+    /// Queue (or resume/retry) the download of the url with the title.
+    Download(String, Option<String>),
+    PauseDownload(String),
+    CancelDownload(String),
+    /// Select the entry of the url in the active or available table.
+    Jump(String),
+    /// Show only the entries of the feed with the title or, if they are shown already, all.
+    FilterFeed(String),
     Refresh,
+    /// Play the most recently played active video.
+    Resume,
+    Execute(Command),
 }
 
 struct Tui<'t> {
     active: ActiveTable<'t>,
     available: AvailableTable<'t>,
+    downloads: DownloadsTable<'t>,
+    view: View,
+    settings: &'t Settings,
 }
-impl Tui<'_> {
+impl<'t> Tui<'t> {
+    /// Available videos added after `new_since` are marked as new.
+    fn new(
+        conn: &Connection,
+        settings: &'t Settings,
+        new_since: Option<DateTime>,
+    ) -> Result<Self, rusqlite::Error> {
+        let view = View {
+            filter: String::new(),
+            feed: None,
+            sort: SortKey::Date,
+        };
+        Ok(Tui {
+            active: ActiveTable::with_active(
+                iter_active(conn, settings.active_order)?,
+                &view,
+                settings,
+            ),
+            available: AvailableTable::with_available(
+                iter_available(conn)?,
+                &view,
+                settings,
+                new_since,
+            ),
+            downloads: DownloadsTable::new(&settings.theme),
+            view,
+            settings,
+        })
+    }
+
     fn update(&mut self, conn: &Connection) -> Result<(), rusqlite::Error> {
-        self.available.update(iter_available(conn)?.into_iter());
-        self.active.update(iter_active(conn)?.into_iter());
+        self.available.update(iter_available(conn)?, &self.view);
+        self.active
+            .update(iter_active(conn, self.settings.active_order)?, &self.view);
+        Ok(())
+    }
+
+    fn rebuild(&mut self) {
+        self.available.rebuild(&self.view);
+        self.active.rebuild(&self.view);
+    }
+
+    fn update_downloads(
+        &mut self,
+        conn: &Connection,
+        downloader: &Downloader,
+    ) -> Result<(), rusqlite::Error> {
+        self.downloads.update(iter_downloads(conn)?, downloader);
         Ok(())
     }
+
+    /// Select the entry of the url in the active or available table. Returns the table that
+    /// contains it.
+    fn select(&mut self, url: &str) -> Option<TuiComponents> {
+        if select_row(&mut self.active.table, url, |r| &r.data.url) {
+            Some(TuiComponents::Active)
+        } else if select_row(&mut self.available.table, url, |r| &r.data.url) {
+            Some(TuiComponents::Available)
+        } else {
+            None
+        }
+    }
+}
+
+/// Access to the tui state without a terminal, used by the benchmarks.
+#[doc(hidden)]
+pub struct Bench<'t>(Tui<'t>);
+
+impl<'t> Bench<'t> {
+    pub fn new(conn: &Connection, settings: &'t Settings) -> Result<Self, rusqlite::Error> {
+        Ok(Bench(Tui::new(conn, settings, None)?))
+    }
+
+    pub fn update(&mut self, conn: &Connection) -> Result<(), rusqlite::Error> {
+        self.0.update(conn)
+    }
+
+    pub fn filter(&mut self, filter: &str) {
+        self.0.view.filter = filter.to_owned();
+        self.0.rebuild();
+    }
+
+    pub fn rebuild(&mut self) {
+        self.0.rebuild();
+    }
 }
 
 impl ContainerProvider for Tui<'_> {
@@ -348,6 +1124,7 @@ impl ContainerProvider for Tui<'_> {
         match index {
             &TuiComponents::Available => &self.available,
             &TuiComponents::Active => &self.active,
+            &TuiComponents::Downloads => &self.downloads,
         }
     }
     fn get_mut<'a, 'b: 'a>(
@@ -357,6 +1134,7 @@ impl ContainerProvider for Tui<'_> {
         match index {
             &TuiComponents::Available => &mut self.available,
             &TuiComponents::Active => &mut self.active,
+            &TuiComponents::Downloads => &mut self.downloads,
         }
     }
     const DEFAULT_CONTAINER: TuiComponents = TuiComponents::Active;
@@ -365,33 +1143,257 @@ impl ContainerProvider for Tui<'_> {
 enum TuiComponents {
     Available,
     Active,
+    Downloads,
 }
 
 enum InputLoopMsg {
     Continue,
 }
 
-pub fn run(conn: &Connection, mpv_binary: &str, theme: &Theme) -> Result<(), rusqlite::Error> {
-    refresh(&conn)?;
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Normal,
+    Filter,
+    Command,
+    Messages,
+}
+
+/// The message shown in the status line and a log of all messages of the session.
+struct Messages {
+    current: Option<String>,
+    log: LogViewer,
+}
+
+impl Messages {
+    fn new() -> Self {
+        Messages {
+            current: None,
+            log: LogViewer::new(),
+        }
+    }
 
-    let mut tui = Tui {
-        active: ActiveTable::with_active(iter_active(&conn)?.into_iter(), theme),
-        available: AvailableTable::with_available(iter_available(&conn)?.into_iter(), theme),
+    fn show(&mut self, message: String) {
+        let time = chrono::Local::now().format("%H:%M:%S");
+        let _ = writeln!(self.log, "{} {}", time, message);
+        self.current = Some(message);
+    }
+
+    fn error(&mut self, error: crate::Error) {
+        self.show(format!("Error: {}", error));
+    }
+
+    fn clear(&mut self) {
+        self.current = None;
+    }
+}
+
+enum Command {
+    AddFeed(AddFeed),
+    Filter(String),
+    Sort(SortKey),
+    Refresh(Option<String>),
+    Errors,
+    Feeds,
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut split = line.trim().splitn(2, char::is_whitespace);
+        let command = split.next().unwrap_or("");
+        let args = split.next().unwrap_or("").trim();
+        match command {
+            // Reuse the argument parser of `uvp add feed` (with the command as "binary name").
+            "addfeed" => AddFeed::from_iter_safe(line.split_whitespace())
+                .map(Command::AddFeed)
+                .map_err(|e| e.message.lines().next().unwrap_or("").to_owned()),
+            "filter" => Ok(Command::Filter(args.to_owned())),
+            "sort" => Ok(Command::Sort(args.parse()?)),
+            "refresh" => Ok(Command::Refresh(if args.is_empty() {
+                None
+            } else {
+                Some(args.to_owned())
+            })),
+            "errors" => Ok(Command::Errors),
+            "feeds" => Ok(Command::Feeds),
+            o => Err(format!("Unknown command '{}'", o)),
+        }
+    }
+}
+
+/// Execute a command entered in command mode. Returns a message for the user, if any.
+fn execute(
+    conn: &Connection,
+    tui: &mut Tui,
+    command: Command,
+) -> Result<Option<String>, rusqlite::Error> {
+    let msg = match command {
+        Command::AddFeed(add) => {
+            let auth = add.auth();
+            let feed: Feed = add.into();
+            ignore_constraint_errors(add_to_feed(conn, &feed))?;
+            if auth.is_some() {
+                set_feed_auth(conn, &feed.url, auth.as_ref())?;
+            }
+            let msg = format!("Added feed {}", feed.title);
+            let report = refresh_feeds(conn, vec![feed], tui.settings)?;
+            tui.update(conn)?;
+            let error = report
+                .failed()
+                .find_map(|f| f.error.as_ref().map(|e| e.to_string()));
+            Some(match error {
+                Some(error) => format!("{}, but {}", msg, error),
+                None => msg,
+            })
+        }
+        Command::Filter(filter) => {
+            tui.view.filter = filter;
+            tui.rebuild();
+            None
+        }
+        Command::Sort(key) => {
+            tui.view.sort = key;
+            tui.rebuild();
+            None
+        }
+        Command::Refresh(None) => {
+            let report = refresh(conn, tui.settings)?;
+            tui.update(conn)?;
+            Some(report.summary())
+        }
+        Command::Refresh(Some(feed)) => {
+            if let Some(feed) = find_feed(conn, &feed)? {
+                let report = refresh_feeds(conn, vec![feed], tui.settings)?;
+                tui.update(conn)?;
+                Some(report.summary())
+            } else {
+                Some(format!("No feed with url or title '{}'", feed))
+            }
+        }
+        Command::Errors => {
+            let mut failing = Vec::new();
+            for feed in iter_feeds(conn)? {
+                if let Some(error) = feed_error(conn, &feed.url)? {
+                    failing.push(format!(
+                        "{}: {} (first failed {})",
+                        feed.title,
+                        error.message,
+                        tui.settings.date_format.format(&error.since)
+                    ));
+                }
+            }
+            Some(if failing.is_empty() {
+                "No failing feeds".to_owned()
+            } else {
+                failing.join("; ")
+            })
+        }
+        Command::Feeds => {
+            let format = |date: Option<DateTime>| {
+                date.map_or("never".to_owned(), |d| tui.settings.date_format.format(&d))
+            };
+            let mut feeds = Vec::new();
+            for feed in iter_feeds(conn)? {
+                feeds.push(format!(
+                    "{}: last video {}, fetched {}",
+                    feed.title,
+                    format(feed.lastupdate),
+                    format(lastfetched(conn, &feed.url)?)
+                ));
+            }
+            Some(feeds.join("; "))
+        }
+    };
+    Ok(msg)
+}
+
+fn open_in_browser(browser_binary: &str, url: &str) -> std::io::Result<()> {
+    let mut child = std::process::Command::new(browser_binary)
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    // Do not block the tui while the browser is running, but reap the process once it exits.
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Pass the text on stdin to the clipboard command (e.g., `wl-copy`, `xclip` or `pbcopy`).
+fn copy_to_clipboard(clipboard_command: &str, text: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(clipboard_command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("exited with {}", status),
+        ));
+    }
+    Ok(())
+}
+
+/// Number of previous filters that are kept.
+const FILTER_HISTORY_LENGTH: usize = 100;
+
+/// Status line for the video that is being played (e.g. by `uvp play` in another terminal).
+fn now_playing_title(conn: &Connection) -> Result<Option<String>, rusqlite::Error> {
+    let url = if let Some(url) = now_playing(conn)? {
+        url
+    } else {
+        return Ok(None);
     };
+    let title = find_in_active(conn, &url)?
+        .and_then(|a| a.title)
+        .or(find_in_available(conn, &url)?.map(|a| a.title))
+        .unwrap_or(url);
+    Ok(Some(format!("Playing: {}", title)))
+}
+
+pub fn run(
+    conn: &Connection,
+    settings: &Settings,
+    profile: &mut StartupProfile,
+) -> Result<(), rusqlite::Error> {
+    let last_session = last_tui_session(conn)?;
+    set_last_tui_session(conn, &chrono::Local::now().into())?;
+    let report = refresh(&conn, settings)?;
+    profile.phase_done("refresh");
+
+    let theme = &settings.theme;
+
+    let mut tui = Tui::new(conn, settings, last_session)?;
+    let mut downloader = Downloader::new();
+    let mut prober = Prober::new(None);
+    let mut probe = settings.probe_durations;
+    tui.update_downloads(conn, &downloader)?;
+    profile.phase_done("store load");
 
     if tui.available.table.rows().is_empty() && tui.active.table.rows().is_empty() {
         eprintln!("Neither active nor available entries. Have you added any feeds, yet?");
         return Ok(());
     }
 
+    // stderr would end up in the middle of the tui, errors of hooks are shown as messages instead.
+    settings.hooks.collect_errors();
     let stdout = std::io::stdout();
     let mut term = unsegen::base::Terminal::new(stdout.lock()).unwrap();
 
-    let layout = HSplit::new(vec![
-        (Box::new(Leaf::new(TuiComponents::Active)), 1.0),
-        (Box::new(Leaf::new(TuiComponents::Available)), 1.0),
-    ]);
-    let mut manager = ContainerManager::<Tui>::from_layout(Box::new(layout));
+    let mut layout = PaneLayout {
+        split: settings.layout,
+        ratio: settings.layout_ratio,
+        zoomed: None,
+    };
+    let mut manager = ContainerManager::<Tui>::from_layout(layout.build());
+    let mut relayout = false;
 
     let (signals_sender, tui_receiver) = std::sync::mpsc::sync_channel(0);
     let (input_continue_sender, input_continue_receiver) = std::sync::mpsc::sync_channel(0);
@@ -413,6 +1415,14 @@ pub fn run(conn: &Connection, mpv_binary: &str, theme: &Theme) -> Result<(), rus
         }
     });
 
+    let tick_sender = signals_sender.clone();
+    let _ticker = std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        if tick_sender.send(Msg::Tick).is_err() {
+            break;
+        }
+    });
+
     let signals = Signals::new(&[signal_hook::SIGWINCH]).unwrap();
     let _signal_handler = std::thread::spawn(move || {
         for signal in signals.forever() {
@@ -428,77 +1438,432 @@ pub fn run(conn: &Connection, mpv_binary: &str, theme: &Theme) -> Result<(), rus
     });
     let (mut work_sender, work_receiver) = std::sync::mpsc::sync_channel(1);
 
+    let mut mode = Mode::Normal;
+    let mut filter_prompt = PromptLine::with_prompt("/".to_owned());
+    // Previous filters are available via Up/Down.
+    for filter in filter_history(conn, FILTER_HISTORY_LENGTH)? {
+        filter_prompt.set(&filter);
+        filter_prompt.finish_line();
+    }
+    let mut command_prompt = PromptLine::with_prompt(":".to_owned());
+    let mut messages = Messages::new();
+    messages.show(report.summary());
+    let mut playing = now_playing_title(conn)?;
+    // A video that is being played elsewhere and is played anyway if it is selected again.
+    let mut confirm_play: Option<String> = None;
+
+    let mut profile = Some(profile);
     let mut run = true;
     while run {
         {
             let win = term.create_root_window();
-            manager.draw(
-                win,
-                &mut tui,
-                StyleModifier::new().fg_color(Color::Yellow),
-                RenderingHints::default(),
-            );
+            let bottom_line: Option<Box<dyn Widget>> = match mode {
+                Mode::Normal | Mode::Messages => messages
+                    .current
+                    .as_ref()
+                    .or(playing.as_ref())
+                    .map(|m| Box::new(m.as_str()) as Box<dyn Widget>),
+                Mode::Filter => Some(Box::new(filter_prompt.as_widget())),
+                Mode::Command => Some(Box::new(command_prompt.as_widget())),
+            };
+            let win = if let Some(bottom_line) = bottom_line {
+                let split_pos = win.get_height().from_origin() - 1;
+                match win.split(split_pos) {
+                    Ok((win, mut bottom)) => {
+                        bottom.set_default_style(
+                            StyleModifier::new()
+                                .fg_color(theme.status_fg)
+                                .bg_color(theme.status_bg)
+                                .apply_to_default(),
+                        );
+                        bottom.clear();
+                        bottom_line.draw(bottom, RenderingHints::default());
+                        win
+                    }
+                    Err(win) => win,
+                }
+            } else {
+                win
+            };
+            if mode == Mode::Messages {
+                messages
+                    .log
+                    .as_widget()
+                    .draw(win, RenderingHints::default());
+            } else {
+                manager.draw(
+                    win,
+                    &mut tui,
+                    StyleModifier::new().fg_color(theme.focus_border),
+                    RenderingHints::default(),
+                );
+            }
         }
         term.present();
+        if let Some(profile) = profile.take() {
+            profile.phase_done("first draw");
+        }
 
         let mut input_continue_msg = None;
         if let Ok(msg) = tui_receiver.recv() {
             match msg {
                 Msg::Input(input) => {
-                    input
-                        .chain((Key::Char('q'), || run = false))
-                        .chain((Key::Char('r'), || {
-                            work_sender.send(TuiMsg::Refresh).unwrap()
-                        }))
-                        .chain(manager.active_container_behavior(&mut tui, &mut work_sender))
-                        .chain(
-                            NavigateBehavior::new(&mut manager.navigatable(&mut tui))
-                                .left_on(Key::Char('h'))
-                                .left_on(Key::Left)
-                                .right_on(Key::Char('l'))
-                                .right_on(Key::Right),
-                        );
+                    match mode {
+                        Mode::Normal => {
+                            messages.clear();
+                            input
+                                .chain((Key::Char('q'), || run = false))
+                                .chain((Key::Char('r'), || {
+                                    work_sender.send(TuiMsg::Refresh).unwrap()
+                                }))
+                                .chain((Key::Char('C'), || {
+                                    work_sender.send(TuiMsg::Resume).unwrap()
+                                }))
+                                .chain((Key::Char('/'), || {
+                                    filter_prompt.set(tui.view.filter.as_str());
+                                    mode = Mode::Filter;
+                                }))
+                                .chain((Key::Char(':'), || mode = Mode::Command))
+                                .chain((Key::Char('M'), || mode = Mode::Messages))
+                                .chain((Key::Char('L'), || {
+                                    layout.split = match layout.split {
+                                        Split::Horizontal => Split::Vertical,
+                                        Split::Vertical => Split::Horizontal,
+                                    };
+                                    relayout = true;
+                                }))
+                                .chain((Key::Char('<'), || {
+                                    layout.ratio = (layout.ratio - RATIO_STEP).max(RATIO_STEP);
+                                    relayout = true;
+                                }))
+                                .chain((Key::Char('>'), || {
+                                    layout.ratio =
+                                        (layout.ratio + RATIO_STEP).min(1.0 - RATIO_STEP);
+                                    relayout = true;
+                                }))
+                                .chain((Key::Char('z'), || {
+                                    layout.zoomed = match layout.zoomed {
+                                        Some(_) => None,
+                                        None => Some(manager.active()),
+                                    };
+                                    relayout = true;
+                                }))
+                                .chain(
+                                    manager.active_container_behavior(&mut tui, &mut work_sender),
+                                )
+                                .chain(
+                                    NavigateBehavior::new(&mut manager.navigatable(&mut tui))
+                                        .left_on(Key::Char('h'))
+                                        .left_on(Key::Left)
+                                        .right_on(Key::Char('l'))
+                                        .right_on(Key::Right)
+                                        .up_on(Key::Char('K'))
+                                        .down_on(Key::Char('J')),
+                                );
+                        }
+                        Mode::Messages => {
+                            input
+                                .chain((&[Key::Esc, Key::Char('q'), Key::Char('M')][..], || {
+                                    mode = Mode::Normal
+                                }))
+                                .chain(
+                                    ScrollBehavior::new(&mut messages.log)
+                                        .forwards_on(Key::Char('j'))
+                                        .forwards_on(Key::Down)
+                                        .backwards_on(Key::Char('k'))
+                                        .backwards_on(Key::Up)
+                                        .to_beginning_on(Key::Char('g'))
+                                        .to_end_on(Key::Char('G')),
+                                );
+                        }
+                        Mode::Filter | Mode::Command => {
+                            let prompt_mode = mode;
+                            let prompt = if mode == Mode::Filter {
+                                &mut filter_prompt
+                            } else {
+                                &mut command_prompt
+                            };
+                            let mut submitted = None;
+                            input
+                                .chain((Key::Esc, || {
+                                    prompt.set("");
+                                    mode = Mode::Normal;
+                                }))
+                                .chain((Key::Char('\n'), || {
+                                    submitted = Some(prompt.finish_line().to_owned());
+                                    mode = Mode::Normal;
+                                }))
+                                .chain(
+                                    EditBehavior::new(&mut *prompt)
+                                        .left_on(Key::Left)
+                                        .right_on(Key::Right)
+                                        .up_on(Key::Up)
+                                        .down_on(Key::Down)
+                                        .delete_forwards_on(Key::Delete)
+                                        .delete_backwards_on(Key::Backspace)
+                                        .go_to_beginning_of_line_on(Key::Home)
+                                        .go_to_end_of_line_on(Key::End)
+                                        .clear_on(Key::Ctrl('u')),
+                                )
+                                .chain(WriteBehavior::new(&mut *prompt));
+
+                            if prompt_mode == Mode::Filter {
+                                if let Some(filter) = submitted.as_ref().filter(|f| !f.is_empty()) {
+                                    if let Err(e) = add_to_filter_history(conn, filter) {
+                                        messages.error(e.into());
+                                    }
+                                }
+                                // The filter is applied while typing, escape discards it.
+                                let filter = submitted
+                                    .unwrap_or_else(|| filter_prompt.active_line().to_owned());
+                                if filter != tui.view.filter {
+                                    tui.view.filter = filter;
+                                    tui.rebuild();
+                                }
+                            } else if let Some(line) = submitted {
+                                match line.parse::<Command>() {
+                                    Ok(command) => {
+                                        work_sender.send(TuiMsg::Execute(command)).unwrap()
+                                    }
+                                    Err(e) => messages.show(e),
+                                }
+                            }
+                        }
+                    }
                     input_continue_msg = Some(InputLoopMsg::Continue);
                 }
                 Msg::Redraw => {}
+                Msg::Tick => {
+                    match downloader.poll(conn, settings) {
+                        Ok(finished) => {
+                            if let Some(download) = finished.last() {
+                                let title = download.title.as_deref().unwrap_or(&download.url);
+                                messages.show(match &download.error {
+                                    None => format!("Downloaded {}", title),
+                                    Some(e) => format!("Failed to download {}: {}", title, e),
+                                });
+                            }
+                        }
+                        Err(e) => messages.show(format!("Download failed: {}", e)),
+                    }
+                    if let Err(e) = tui.update_downloads(conn, &downloader) {
+                        messages.error(e.into());
+                    }
+                    match now_playing_title(conn) {
+                        Ok(p) => playing = p,
+                        Err(e) => messages.error(e.into()),
+                    }
+                    if probe {
+                        match prober.poll(conn, settings) {
+                            Ok(true) => {
+                                if let Err(e) = tui.update(conn) {
+                                    messages.error(e.into());
+                                }
+                            }
+                            Ok(false) => {}
+                            // E.g., yt-dlp is not installed, so there is no point in retrying.
+                            Err(e) => {
+                                messages.show(format!("Probing durations failed: {}", e));
+                                probe = false;
+                            }
+                        }
+                    }
+                }
             }
         }
         if let Ok(msg) = work_receiver.try_recv() {
-            match msg {
-                TuiMsg::Play(url) => {
-                    term.on_main_screen(|| crate::mpv::play(conn, &url, mpv_binary))
-                        .unwrap()?;
-                    tui.update(conn)?;
-                }
-                TuiMsg::Refresh => {
-                    refresh(conn)?;
-                    tui.update(conn)?;
-                }
-                TuiMsg::Delete(url) => {
-                    remove_from_active(conn, &url)?;
-                    remove_from_available(conn, &url)?;
-                    tui.update(conn)?;
-                }
-                TuiMsg::AddAvailable(a) => {
-                    add_to_available(conn, &a)?;
-                    tui.update(conn)?;
-                }
-                TuiMsg::AddActive(a) => {
-                    add_to_active(conn, &a)?;
-                    tui.update(conn)?;
+            // Errors, also those of the database, are shown instead of ending the tui.
+            let handle = || -> Result<(), crate::Error> {
+                let msg = match msg {
+                    TuiMsg::Resume => match most_recently_played(conn)? {
+                        Some(active) => TuiMsg::Play(active.url, false),
+                        None => {
+                            messages.show("No video has been played yet".to_owned());
+                            return Ok(());
+                        }
+                    },
+                    msg => msg,
+                };
+                match msg {
+                    TuiMsg::Play(url, audio_only) => {
+                        let args = if audio_only {
+                            vec!["--no-video".to_owned()]
+                        } else {
+                            Vec::new()
+                        };
+                        let force = confirm_play.take().as_deref() == Some(url.as_str());
+                        match term
+                            .on_main_screen(|| {
+                                crate::player::play(conn, &url, settings, &args, force)
+                            })
+                            .unwrap()
+                        {
+                            Ok(crate::player::Outcome::Stopped) => {}
+                            Ok(crate::player::Outcome::Interrupted) => run = false,
+                            Ok(crate::player::Outcome::Failed(e)) => messages.show(e),
+                            Ok(crate::player::Outcome::InUse(lease)) => {
+                                messages.show(format!(
+                                    "{} is being played by {}, play it again to play it anyway",
+                                    url, lease.holder
+                                ));
+                                confirm_play = Some(url);
+                            }
+                            Err(e) => messages.show(format!("Playback failed: {}", e)),
+                        }
+                        tui.update(conn)?;
+                    }
+                    TuiMsg::PlayFeed(feed_url) => {
+                        match term
+                            .on_main_screen(|| {
+                                crate::player::play_feed(conn, &feed_url, settings, &[])
+                            })
+                            .unwrap()
+                        {
+                            Ok(crate::player::Outcome::Stopped) => {}
+                            Ok(crate::player::Outcome::Interrupted) => run = false,
+                            Ok(crate::player::Outcome::Failed(e)) => messages.show(e),
+                            // Leased videos are left out of playlists.
+                            Ok(crate::player::Outcome::InUse(_)) => unreachable!(),
+                            Err(e) => messages.show(format!("Playback failed: {}", e)),
+                        }
+                        tui.update(conn)?;
+                    }
+                    TuiMsg::Open(url) => {
+                        if let Err(e) = open_in_browser(&settings.browser_binary, &url) {
+                            messages.show(format!("Failed to open {}: {}", url, e));
+                        }
+                    }
+                    TuiMsg::Copy(url) => {
+                        messages.show(match copy_to_clipboard(&settings.clipboard_command, &url) {
+                            Ok(()) => format!("Copied {}", url),
+                            Err(e) => format!("Failed to copy {}: {}", url, e),
+                        });
+                    }
+                    TuiMsg::ActiveInfo(url, mut info) => {
+                        if let Some(update) = position_update(conn, &url)? {
+                            let ago = format_relative(update.updated, chrono::Local::now().into());
+                            info.push_str(&format!(", last watched on {}, {}", update.host, ago));
+                        }
+                        messages.show(info);
+                    }
+                    TuiMsg::AvailableInfo(url, mut info) => {
+                        let feeds = feeds_of_entry(conn, &url)?;
+                        if feeds.len() > 1 {
+                            let titles = feeds.iter().map(|f| f.title.as_str()).collect::<Vec<_>>();
+                            info.push_str(&format!(", in feeds: {}", titles.join(", ")));
+                        }
+                        messages.show(info);
+                    }
+                    TuiMsg::Resume => unreachable!("Resume has been turned into Play above"),
+                    TuiMsg::Refresh => {
+                        messages.show(refresh(conn, settings)?.summary());
+                        tui.update(conn)?;
+                    }
+                    TuiMsg::Delete(url) => {
+                        delete_video(conn, &url)?;
+                        tui.update(conn)?;
+                    }
+                    TuiMsg::Undelete(active) => match last_deleted(conn, active)? {
+                        Some(deleted) => {
+                            let list = restore_deleted(conn, &deleted)?;
+                            tui.update(conn)?;
+                            messages.show(format!(
+                                "Restored {} to the {} videos",
+                                deleted.item.title().unwrap_or(deleted.item.url()),
+                                list
+                            ));
+                        }
+                        None => messages.show("Nothing to restore".to_owned()),
+                    },
+                    TuiMsg::SetPriority(url, priority) => {
+                        set_priority(conn, &url, priority)?;
+                        tui.update(conn)?;
+                    }
+                    TuiMsg::Download(url, title) => {
+                        enqueue_download(conn, &url, title.as_deref())?;
+                        tui.update_downloads(conn, &downloader)?;
+                    }
+                    TuiMsg::PauseDownload(url) => {
+                        match downloader.pause(conn, &url) {
+                            Ok(()) => {}
+                            Err(e) => messages.show(format!("Failed to pause download: {}", e)),
+                        }
+                        tui.update_downloads(conn, &downloader)?;
+                    }
+                    TuiMsg::CancelDownload(url) => {
+                        match downloader.cancel(conn, &url) {
+                            Ok(()) => {}
+                            Err(e) => messages.show(format!("Failed to cancel download: {}", e)),
+                        }
+                        tui.update_downloads(conn, &downloader)?;
+                    }
+                    TuiMsg::Jump(url) => match tui.select(&url) {
+                        Some(component) => manager.set_active(component),
+                        None => messages.show(format!("{} is neither active nor available", url)),
+                    },
+                    TuiMsg::FilterFeed(feed) => {
+                        if tui.view.feed.as_ref() == Some(&feed) {
+                            tui.view.feed = None;
+                        } else {
+                            messages.show(format!("Showing only {} (F to show all feeds)", feed));
+                            tui.view.feed = Some(feed);
+                        }
+                        tui.rebuild();
+                    }
+                    TuiMsg::Execute(command) => {
+                        if let Some(m) = execute(conn, &mut tui, command)? {
+                            messages.show(m);
+                        }
+                    }
                 }
+                Ok(())
+            };
+            if let Err(e) = handle() {
+                messages.error(e);
             }
         }
+        for error in settings.hooks.take_errors() {
+            messages.show(format!("Error: {}", error));
+        }
         if let Some(m) = input_continue_msg {
             input_continue_sender.send(m).unwrap();
         }
 
         // Avoid accidentally focusing empty table
-        if tui.available.table.rows().is_empty() {
-            manager.set_active(TuiComponents::Active);
-        } else if tui.active.table.rows().is_empty() {
-            manager.set_active(TuiComponents::Available);
+        let active_empty = tui.active.table.rows().is_empty();
+        let available_empty = tui.available.table.rows().is_empty();
+        let focused_empty = match manager.active() {
+            TuiComponents::Active => active_empty,
+            TuiComponents::Available => available_empty,
+            TuiComponents::Downloads => tui.downloads.table.rows().is_empty(),
+        };
+        if focused_empty {
+            if !active_empty {
+                manager.set_active(TuiComponents::Active);
+            } else if !available_empty {
+                manager.set_active(TuiComponents::Available);
+            }
+        }
+        // A zoomed layout shows the focused pane, even if the focus has been moved, e.g., by a
+        // jump to another pane.
+        if layout
+            .zoomed
+            .as_ref()
+            .map_or(false, |z| *z != manager.active())
+        {
+            layout.zoomed = Some(manager.active());
+            relayout = true;
+        }
+        if relayout {
+            let active = manager.active();
+            manager.set_layout(layout.build());
+            manager.set_active(active);
+            relayout = false;
         }
     }
+    // Unfinished downloads are continued next time.
+    if let Err(crate::Error::DB(e)) = downloader.stop(conn) {
+        return Err(e);
+    }
     Ok(())
 }